@@ -4,6 +4,7 @@
 //! current best practices and security patterns while providing full functionality
 //! of a standard token plus free mint capabilities.
 
+use alkanes_runtime::auth::AuthenticatedResponder;
 use alkanes_runtime::storage::StoragePointer;
 use alkanes_runtime::{declare_alkane, message::MessageDispatch, runtime::AlkaneResponder};
 use alkanes_support::gz;
@@ -17,6 +18,7 @@ use bitcoin::{Transaction, Txid};
 use metashrew_support::compat::to_arraybuffer_layout;
 use metashrew_support::index_pointer::KeyValuePointer;
 use metashrew_support::utils::consensus_decode;
+use serde::Deserialize;
 use std::io::Cursor;
 use std::sync::Arc;
 
@@ -24,6 +26,12 @@ use std::sync::Arc;
 pub const ALKANE_FACTORY_OWNED_TOKEN_ID: u128 = 0x0fff;
 pub const ALKANE_FACTORY_FREE_MINT_ID: u128 = 0x0ffe;
 
+/// Decimals reported when the metadata does not set them
+pub const DEFAULT_DECIMALS: u8 = 8;
+
+/// Magic bytes of a wasm module, the payload of a deployment
+const WASM_MAGIC: &[u8] = b"\0asm";
+
 /// Returns a StoragePointer for the token name
 fn name_pointer() -> StoragePointer {
     StoragePointer::from_keyword("/name")
@@ -69,6 +77,35 @@ impl TokenName {
     }
 }
 
+/// JSON metadata carried by the witness payload, every field is optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TokenMetadata {
+    pub content_type: Option<String>,
+    pub description: String,
+    pub url: String,
+    pub decimals: Option<u8>,
+}
+
+/// Content type of a decompressed payload, images are recognized by their magic bytes
+pub fn content_type_of(data: &[u8]) -> &'static str {
+    if data.starts_with(b"\x89PNG") {
+        "image/png"
+    } else if data.starts_with(&[0xff, 0xd8, 0xff]) {
+        "image/jpeg"
+    } else if data.starts_with(b"GIF8") {
+        "image/gif"
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP".as_slice()) {
+        "image/webp"
+    } else if data.starts_with(b"<svg") || data.starts_with(b"<?xml") {
+        "image/svg+xml"
+    } else if serde_json::from_slice::<serde_json::Value>(data).is_ok() {
+        "application/json"
+    } else {
+        "application/octet-stream"
+    }
+}
+
 pub struct ContextHandle(());
 
 #[cfg(test)]
@@ -173,6 +210,25 @@ pub trait MintableToken: AlkaneResponder {
         Ok(())
     }
 
+    /// Decrease the total supply
+    fn decrease_total_supply(&self, v: u128) -> Result<()> {
+        self.set_total_supply(
+            overflow_error(self.total_supply().checked_sub(v))
+                .map_err(|_| anyhow!("total supply underflow"))?,
+        );
+        Ok(())
+    }
+
+    /// Get the pointer to the cumulative burned amount
+    fn burned_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/burned")
+    }
+
+    /// Get the cumulative burned amount
+    fn burned(&self) -> u128 {
+        self.burned_pointer().get_value::<u128>()
+    }
+
     /// Mint new tokens
     fn mint(&self, context: &Context, value: u128) -> Result<AlkaneTransfer> {
         self.increase_total_supply(value)?;
@@ -192,14 +248,66 @@ pub trait MintableToken: AlkaneResponder {
         gz::decompress(self.data_pointer().get().as_ref().clone()).unwrap_or_else(|_| vec![])
     }
 
-    /// Set the token data from the transaction
+    /// Set the token data from the gz compressed witness payload of the transaction.
+    ///
+    /// Nothing is stored when there is no such payload, or when it is the contract
+    /// bytecode of a deployment.
     fn set_data(&self) -> Result<()> {
         let tx = consensus_decode::<Transaction>(&mut Cursor::new(CONTEXT.transaction()))?;
         let data: Vec<u8> = find_witness_payload(&tx, 0).unwrap_or_else(|| vec![]);
+        let content = match gz::decompress(data.clone()) {
+            Ok(content) if !content.is_empty() && !content.starts_with(WASM_MAGIC) => content,
+            _ => return Ok(()),
+        };
         self.data_pointer().set(Arc::new(data));
 
+        // JSON 元数据可以覆盖识别出的类型
+        let mut content_type = content_type_of(&content).to_string();
+        if let Ok(metadata) = serde_json::from_slice::<TokenMetadata>(&content) {
+            if let Some(v) = metadata.content_type {
+                content_type = v;
+            }
+            self.description_pointer()
+                .set(Arc::new(metadata.description.into_bytes()));
+            self.url_pointer().set(Arc::new(metadata.url.into_bytes()));
+            if let Some(decimals) = metadata.decimals {
+                self.decimals_pointer().set_value::<u8>(decimals);
+            }
+        }
+        self.content_type_pointer()
+            .set(Arc::new(content_type.into_bytes()));
+
         Ok(())
     }
+
+    /// Get the pointer to the content type of the token data
+    fn content_type_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/content-type")
+    }
+
+    /// Get the pointer to the token description
+    fn description_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/description")
+    }
+
+    /// Get the pointer to the token URL
+    fn url_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/url")
+    }
+
+    /// Get the pointer to the decimals, unset means DEFAULT_DECIMALS
+    fn decimals_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/decimals")
+    }
+
+    /// Get the decimals
+    fn decimals(&self) -> u8 {
+        if self.decimals_pointer().get().is_empty() {
+            DEFAULT_DECIMALS
+        } else {
+            self.decimals_pointer().get_value::<u8>()
+        }
+    }
 }
 
 /// MintableAlkane implements a free mint token contract with security features
@@ -211,7 +319,8 @@ impl MintableToken for MintableAlkane {}
 /// Message enum for opcode-based dispatch
 #[derive(MessageDispatch)]
 enum MintableAlkaneMessage {
-    /// Initialize the token with configuration
+    /// Initialize the token with configuration, an owned token returns an auth token
+    /// instead of the supply and issues the cap over time with MintOwned
     #[opcode(0)]
     Initialize {
         /// Maximum supply cap (0 for unlimited)
@@ -222,8 +331,44 @@ enum MintableAlkaneMessage {
         name_part2: u128,
         /// Token symbol
         symbol: u128,
+        /// Non-zero for an owned token
+        owned: u128,
     },
 
+    /// Initialize a fair-launch token: `premine` goes to the deployer and the rest of
+    /// the supply is minted `value_per_mint` at a time with MintTokens
+    #[opcode(1)]
+    InitializeFreeMint {
+        /// Units minted to the deployer
+        premine: u128,
+        /// Units minted by each MintTokens call
+        value_per_mint: u128,
+        /// Maximum number of MintTokens calls
+        max_mints: u128,
+        /// First height MintTokens is accepted at
+        start_height: u128,
+        /// Last height MintTokens is accepted at (0 for no end)
+        end_height: u128,
+        /// Token name part 1
+        name_part1: u128,
+        /// Token name part 2
+        name_part2: u128,
+        /// Token symbol
+        symbol: u128,
+    },
+
+    /// Mint `value_per_mint` units, at most once per transaction
+    #[opcode(77)]
+    MintTokens,
+
+    /// Mint `value` units to the holder of the auth token, up to the cap
+    #[opcode(78)]
+    MintOwned { value: u128 },
+
+    /// Burn the incoming units of this token, other alkanes are returned
+    #[opcode(88)]
+    Burn,
+
     /// Get the token name
     #[opcode(99)]
     #[returns(String)]
@@ -244,10 +389,50 @@ enum MintableAlkaneMessage {
     #[returns(u128)]
     GetCap,
 
+    /// Get the number of MintTokens calls so far
+    #[opcode(103)]
+    #[returns(u128)]
+    GetMinted,
+
+    /// Get the units minted by each MintTokens call
+    #[opcode(104)]
+    #[returns(u128)]
+    GetValuePerMint,
+
+    /// Get the maximum number of MintTokens calls
+    #[opcode(105)]
+    #[returns(u128)]
+    GetMaxMints,
+
+    /// Get the cumulative burned amount
+    #[opcode(106)]
+    #[returns(u128)]
+    GetBurned,
+
     /// Get the token data
     #[opcode(1000)]
     #[returns(Vec<u8>)]
     GetData,
+
+    /// Get the content type of the token data
+    #[opcode(1001)]
+    #[returns(String)]
+    GetContentType,
+
+    /// Get the token description
+    #[opcode(1002)]
+    #[returns(String)]
+    GetDescription,
+
+    /// Get the token URL
+    #[opcode(1003)]
+    #[returns(String)]
+    GetUrl,
+
+    /// Get the decimals
+    #[opcode(1004)]
+    #[returns(u8)]
+    GetDecimals,
 }
 
 impl MintableAlkane {
@@ -276,6 +461,41 @@ impl MintableAlkane {
     }
 
 
+    /// Get the pointer to the units minted per mint
+    pub fn value_per_mint_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/value-per-mint")
+    }
+
+    /// Get the units minted per mint (0 when free mint is disabled)
+    pub fn value_per_mint(&self) -> u128 {
+        self.value_per_mint_pointer().get_value::<u128>()
+    }
+
+    /// Get the pointer to the maximum mint count
+    pub fn max_mints_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/max-mints")
+    }
+
+    /// Get the maximum mint count
+    pub fn max_mints(&self) -> u128 {
+        self.max_mints_pointer().get_value::<u128>()
+    }
+
+    /// Get the pointer to the first mint height
+    pub fn mint_start_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/mint-start")
+    }
+
+    /// Get the pointer to the last mint height (0 for no end)
+    pub fn mint_end_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/mint-end")
+    }
+
+    /// Get the pointer to the owned mode flag
+    pub fn owned_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/owned")
+    }
+
     /// Get the pointer to the supply cap
     pub fn cap_pointer(&self) -> StoragePointer {
         StoragePointer::from_keyword("/cap")
@@ -315,6 +535,7 @@ impl MintableAlkane {
         name_part1: u128,
         name_part2: u128,
         symbol: u128,
+        owned: u128,
     ) -> Result<CallResponse> {
         let context = self.context()?;
         let mut response = CallResponse::forward(&context.incoming_alkanes);
@@ -325,18 +546,155 @@ impl MintableAlkane {
 
         // Set configuration
         self.set_cap(cap);
-        // self.set_data()?;
+        self.set_data()?;
 
         // Create TokenName from the two parts
         let name = TokenName::new(name_part1, name_part2);
         <Self as MintableToken>::set_name_and_symbol(self, name, symbol);
 
-        // Mint all tokens
-        response.alkanes.0.push(self.mint(&context, cap)?);
+        if owned != 0 {
+            // Supply is minted with the auth token
+            self.owned_pointer().set_value::<u8>(1);
+            response.alkanes.0.push(self.deploy_auth_token(1u128)?);
+        } else {
+            // Mint all tokens
+            response.alkanes.0.push(self.mint(&context, cap)?);
+        }
         
         Ok(response)
     }
 
+    /// Initialize a free mint token, the cap is the premine plus every mint
+    fn initialize_free_mint(
+        &self,
+        premine: u128,
+        value_per_mint: u128,
+        max_mints: u128,
+        start_height: u128,
+        end_height: u128,
+        name_part1: u128,
+        name_part2: u128,
+        symbol: u128,
+    ) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+
+        // Prevent multiple initializations
+        self.observe_initialization()
+            .map_err(|_| anyhow!("Contract already initialized"))?;
+
+        if value_per_mint == 0 || max_mints == 0 {
+            return Err(anyhow!("value per mint and max mints must be set"));
+        }
+        if end_height != 0 && end_height < start_height {
+            return Err(anyhow!("mint ends before it starts"));
+        }
+        let cap = overflow_error(
+            value_per_mint
+                .checked_mul(max_mints)
+                .and_then(|v| v.checked_add(premine)),
+        )
+        .map_err(|_| anyhow!("cap overflow"))?;
+
+        // Set configuration
+        self.set_cap(cap);
+        self.set_data()?;
+        self.value_per_mint_pointer().set_value::<u128>(value_per_mint);
+        self.max_mints_pointer().set_value::<u128>(max_mints);
+        self.mint_start_pointer().set_value::<u64>(start_height.try_into()?);
+        self.mint_end_pointer().set_value::<u64>(end_height.try_into()?);
+
+        let name = TokenName::new(name_part1, name_part2);
+        <Self as MintableToken>::set_name_and_symbol(self, name, symbol);
+
+        if premine > 0 {
+            response.alkanes.0.push(self.mint(&context, premine)?);
+        }
+
+        Ok(response)
+    }
+
+    /// Mint `value` units, authenticated with the auth token which is handed back
+    fn mint_owned(&self, value: u128) -> Result<CallResponse> {
+        if self.owned_pointer().get_value::<u8>() != 1 {
+            return Err(anyhow!("token is not owned"));
+        }
+        self.only_owner()?;
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+
+        if overflow_error(self.total_supply().checked_add(value))? > self.cap() {
+            return Err(anyhow!("supply cap reached"));
+        }
+        response.alkanes.0.push(self.mint(&context, value)?);
+
+        Ok(response)
+    }
+
+    /// Mint `value_per_mint` units within the mint window, once per transaction
+    fn mint_tokens(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+
+        let value = self.value_per_mint();
+        if value == 0 {
+            return Err(anyhow!("free mint not enabled"));
+        }
+        let height = self.height();
+        if height < self.mint_start_pointer().get_value::<u64>() {
+            return Err(anyhow!("mint not started"));
+        }
+        let end = self.mint_end_pointer().get_value::<u64>();
+        if end != 0 && height > end {
+            return Err(anyhow!("mint ended"));
+        }
+        if self.minted() >= self.max_mints() {
+            return Err(anyhow!("all mints claimed"));
+        }
+        if overflow_error(self.total_supply().checked_add(value))? > self.cap() {
+            return Err(anyhow!("supply cap reached"));
+        }
+
+        let txid = context.transaction_id()?;
+        if self.has_tx_hash(&txid) {
+            return Err(anyhow!("already minted in this transaction"));
+        }
+        self.add_tx_hash(&txid)?;
+        self.increment_mint()?;
+
+        response.alkanes.0.push(self.mint(&context, value)?);
+
+        Ok(response)
+    }
+
+    /// Burn the incoming units of this token, they stay with the contract and leave
+    /// the total supply
+    fn burn(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+
+        let value = overflow_error(
+            context
+                .incoming_alkanes
+                .0
+                .iter()
+                .filter(|transfer| transfer.id == context.myself)
+                .try_fold(0u128, |acc, transfer| acc.checked_add(transfer.value)),
+        )?;
+        if value == 0 {
+            return Err(anyhow!("no tokens supplied to burn"));
+        }
+
+        self.decrease_total_supply(value)?;
+        self.burned_pointer().set_value::<u128>(
+            overflow_error(self.burned().checked_add(value))
+                .map_err(|_| anyhow!("burned overflow"))?,
+        );
+        response.alkanes.0.retain(|transfer| transfer.id != context.myself);
+
+        Ok(response)
+    }
+
     /// Set the token name and symbol
     fn set_name_and_symbol(
         &self,
@@ -395,6 +753,46 @@ impl MintableAlkane {
     }
 
 
+    /// Get the number of mints so far
+    fn get_minted(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+
+        response.data = self.minted().to_le_bytes().to_vec();
+
+        Ok(response)
+    }
+
+    /// Get the units minted per mint
+    fn get_value_per_mint(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+
+        response.data = self.value_per_mint().to_le_bytes().to_vec();
+
+        Ok(response)
+    }
+
+    /// Get the maximum mint count
+    fn get_max_mints(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+
+        response.data = self.max_mints().to_le_bytes().to_vec();
+
+        Ok(response)
+    }
+
+    /// Get the cumulative burned amount
+    fn get_burned(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+
+        response.data = self.burned().to_le_bytes().to_vec();
+
+        Ok(response)
+    }
+
     /// Get the token data
     fn get_data(&self) -> Result<CallResponse> {
         let context = self.context()?;
@@ -404,10 +802,52 @@ impl MintableAlkane {
 
         Ok(response)
     }
+
+    /// Get the content type of the token data
+    fn get_content_type(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+
+        response.data = self.content_type_pointer().get().as_ref().clone();
+
+        Ok(response)
+    }
+
+    /// Get the token description
+    fn get_description(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+
+        response.data = self.description_pointer().get().as_ref().clone();
+
+        Ok(response)
+    }
+
+    /// Get the token URL
+    fn get_url(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+
+        response.data = self.url_pointer().get().as_ref().clone();
+
+        Ok(response)
+    }
+
+    /// Get the decimals
+    fn get_decimals(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+
+        response.data = (self.decimals() as u128).to_le_bytes().to_vec();
+
+        Ok(response)
+    }
 }
 
 impl AlkaneResponder for MintableAlkane {}
 
+impl AuthenticatedResponder for MintableAlkane {}
+
 // Use the MessageDispatch macro for opcode handling
 declare_alkane! {
     impl AlkaneResponder for MintableAlkane {
@@ -67,6 +67,33 @@ enum OrbitalInstanceMessage {
 
     #[opcode(1005)]
     Claim,
+
+    #[opcode(1006)]
+    ClaimReferral,
+
+    /// Add the tokens sent along with the orbital to the staked position, a BRC-20
+    /// position is topped up with the deposit proof in the witness
+    #[opcode(1007)]
+    TopUp,
+
+    #[opcode(1008)]
+    ExtendPeriod { period: u128 },
+
+    /// Split `value` of the position off into a new orbital
+    #[opcode(1009)]
+    Split { value: u128 },
+
+    /// Merge the position of the orbital sent along into this one
+    #[opcode(1010)]
+    Merge,
+
+    /// Turn auto-renewal of the position on (non-zero) or off
+    #[opcode(1011)]
+    SetAutoRenew { enabled: u128 },
+
+    /// Exit the position while the pool is in emergency mode
+    #[opcode(1012)]
+    EmergencyWithdraw,
 }
 
 impl Token for OrbitalInstance {
@@ -263,7 +290,25 @@ impl OrbitalInstance {
 
         let call_response =
             self.call(&cellpack, &AlkaneTransferParcel::default(), self.fuel())?;
+        //质押的 coin 随解质押退回
+        response.alkanes.0.extend(call_response.alkanes.0);
+        response.data = call_response.data;
+        Ok(response)
+    }
+
+    fn emergency_withdraw(&self) -> Result<CallResponse> {
+        self.only_owner()?;
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        let collection_id = self.collection_ref();
+        let cellpack = Cellpack {
+            target: collection_id,
+            inputs: vec![64, self.index()],
+        };
 
+        let call_response =
+            self.call(&cellpack, &AlkaneTransferParcel::default(), self.fuel())?;
+        response.alkanes.0.extend(call_response.alkanes.0);
         response.data = call_response.data;
         Ok(response)
     }
@@ -288,6 +333,113 @@ impl OrbitalInstance {
         Ok(response)
     }
 
+    fn claim_referral(&self) -> Result<CallResponse> {
+        self.only_owner()?;
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        let collection_id = self.collection_ref();
+
+        let cellpack = Cellpack {
+            target: collection_id,
+            inputs: vec![56, self.index()],
+        };
+
+        let call_response =
+            self.call(&cellpack, &AlkaneTransferParcel::default(), self.fuel())?;
+        if call_response.alkanes.0.len() >0 {
+            response.alkanes.0.push(call_response.alkanes.0[0]);
+        }
+        response.data = call_response.data;
+        Ok(response)
+    }
+
+    fn top_up(&self) -> Result<CallResponse> {
+        self.call_with_alkanes(vec![59, self.index()])
+    }
+
+    fn merge(&self) -> Result<CallResponse> {
+        self.call_with_alkanes(vec![62, self.index()])
+    }
+
+    //自身 token 用于认证，其他 alkane 随调用转给质押池
+    fn call_with_alkanes(&self, inputs: Vec<u128>) -> Result<CallResponse> {
+        let context = self.context()?;
+        let (own, tokens): (Vec<AlkaneTransfer>, Vec<AlkaneTransfer>) = context
+            .incoming_alkanes
+            .0
+            .iter()
+            .cloned()
+            .partition(|transfer| transfer.id == context.myself);
+        if own.iter().map(|transfer| transfer.value).sum::<u128>() < 1 {
+            return Err(anyhow!("supplied alkane is not authentication token"));
+        }
+        let mut response = CallResponse::forward(&AlkaneTransferParcel(own));
+        let collection_id = self.collection_ref();
+
+        let cellpack = Cellpack {
+            target: collection_id,
+            inputs,
+        };
+
+        let call_response =
+            self.call(&cellpack, &AlkaneTransferParcel(tokens), self.fuel())?;
+        response.alkanes.0.extend(call_response.alkanes.0);
+        response.data = call_response.data;
+        Ok(response)
+    }
+
+    fn extend_period(&self, period: u128) -> Result<CallResponse> {
+        self.only_owner()?;
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        let collection_id = self.collection_ref();
+
+        let cellpack = Cellpack {
+            target: collection_id,
+            inputs: vec![60, self.index(), period],
+        };
+
+        let call_response =
+            self.call(&cellpack, &AlkaneTransferParcel::default(), self.fuel())?;
+        response.data = call_response.data;
+        Ok(response)
+    }
+
+    fn set_auto_renew(&self, enabled: u128) -> Result<CallResponse> {
+        self.only_owner()?;
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        let collection_id = self.collection_ref();
+
+        let cellpack = Cellpack {
+            target: collection_id,
+            inputs: vec![63, self.index(), enabled],
+        };
+
+        let call_response =
+            self.call(&cellpack, &AlkaneTransferParcel::default(), self.fuel())?;
+        response.data = call_response.data;
+        Ok(response)
+    }
+
+    fn split(&self, value: u128) -> Result<CallResponse> {
+        self.only_owner()?;
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        let collection_id = self.collection_ref();
+
+        let cellpack = Cellpack {
+            target: collection_id,
+            inputs: vec![61, self.index(), value],
+        };
+
+        let call_response =
+            self.call(&cellpack, &AlkaneTransferParcel::default(), self.fuel())?;
+        response.alkanes.0.extend(call_response.alkanes.0);
+        response.data = call_response.data;
+        Ok(response)
+    }
+
     // Helper functions
     /// Set the collection Alkane ID
     fn set_collection_alkane_id(&self, id: &AlkaneId) {
@@ -25,9 +25,22 @@ use std::f32::consts::E;
 use std::io::Cursor;
 use std::sync::Arc;
 use types_support::{
+    amount::format_amount,
+    config::{
+        validate_token_whitelist, Brc20Ticker, PenaltyDestination, ALKANE_DECIMALS,
+        PeriodWeight, PoolConfig, StakeToken,
+    },
+    proof::{validate_next_header, Brc20Deposit, DepositProof, BRC20_DECIMALS},
+    staking::Redemption,
     staking::Staking,
+    staking::{ALKANE_STAKING_INDEX, STAKING_VERSION},
+    staking::StakingAcc,
+    staking::WeightSegment,
     staking::StakingStat,
 };
+use bitcoin::block::Header;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::Script;
 use std::cmp::{max, min};
 use rust_decimal::Decimal;
 use std::str::FromStr;
@@ -43,23 +56,21 @@ const WHITELIST_MINT_START_TM: u64 = 902536;
 const PUBLIC_MINT_START_TM: u64 = 902566;
 
 
-const CAP: u128 = 100000000000000000;
-const MINING_CAP: u64 = 52000000000000000;
-const MINING_ONE_BLOCK_VOLUME: u64 = 1003086419753;
-const MINING_ONE_DAY_VOLUME: u64 = 144444444444444;
-const MINING_FIRST_HEIGHT: u64 = 450;  //挖矿的第一个块高度
-const MINING_LAST_HEIGHT: u64 = MINING_FIRST_HEIGHT + 144*360-1; //挖矿的最后块高度
-const MIN_STAKING_VALUE: u64 = 1000;
-const PROFIT_RELEASE_HEIGHT: u64 = 144*180;
-const PROFIT_RELEASE_DAY: u64 = 180;
+const BRC20_NAME_0: &str = "sats";
+
+/// 存款证明所在区块之后至少需要跟踪到的区块数（含自身）
+const MIN_PROOF_CONFIRMATIONS: u64 = 6;
 
-const COIN_TEMPLATE_ID: u128 = 3; //TODO 部署代码后得到模板ID
-const COIN_SYMBOL: &str = "forge";
-const COIN_NAME: &str = "Alkanes Forge";
+/// 提交的区块头最多可以从跟踪链末尾往回这么多个区块处分叉，不会回滚已确认的存款
+const MAX_REORG_DEPTH: u64 = MIN_PROOF_CONFIRMATIONS - 1;
 
-const ORBITAL_TEMPLATE_ID: u128 = 1;
+/// 存储格式版本，0 为没有记录版本的旧存储
+///
+/// 1：质押记录带版本前缀，周期权重为定长编码，开始计算收益的天显式保存
+const SCHEMA_VERSION: u32 = 1;
 
-const BRC20_NAME_0: &str = "sats";
+/// 可以暂停的用户操作
+const PAUSABLE_OPCODES: [u128; 12] = [50, 51, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63];
 
 
 /// Collection Contract Structure
@@ -74,7 +85,8 @@ impl AlkaneResponder for StakingPool {}
 /// These messages define the available operations that can be performed on the contract
 #[derive(MessageDispatch)]
 enum StakingPoolMessage {
-    /// Initialize the contract and perform premine
+    /// Initialize the contract and perform premine, the pool config is read as JSON
+    /// from the witness (defaults when empty)
     #[opcode(0)]
     Initialize,
 
@@ -94,6 +106,128 @@ enum StakingPoolMessage {
     #[opcode(54)]
     Claim,
 
+    /// Claim the referral rewards of the calling orbital
+    #[opcode(56)]
+    ClaimReferral,
+
+    /// Stake with a BRC-20 deposit proof in the witness instead of the auth token, the
+    /// inscription must have been attested with AttestDeposits and the transaction must
+    /// spend an output of the deposit transaction other than the deposit
+    #[opcode(55)]
+    StakingWithProof,
+
+    /// Stake the incoming coins for `period` days, they are returned on unstaking
+    #[opcode(57)]
+    Compound { period: u128 },
+
+    /// Stake the incoming whitelisted alkane token for `period` days, the tokens are
+    /// held by the pool and returned on unstaking
+    #[opcode(58)]
+    StakeToken { period: u128 },
+
+    /// Add the incoming tokens to the calling orbital's position, a BRC-20 position is
+    /// topped up with an attested deposit proof in the witness like StakingWithProof
+    #[opcode(59)]
+    TopUp { index: u128 },
+
+    /// Lock the calling orbital's position for a longer period of the weight table
+    #[opcode(60)]
+    ExtendPeriod { index: u128, period: u128 },
+
+    /// Split `value` of the calling orbital's position off into a new orbital
+    #[opcode(61)]
+    Split { index: u128, value: u128 },
+
+    /// Merge the position of the orbital sent along into the calling orbital's position
+    #[opcode(62)]
+    Merge { index: u128 },
+
+    /// Turn auto-renewal of the calling orbital's position on or off, a renewing
+    /// position rolls into another period of the same length when it expires
+    #[opcode(63)]
+    SetAutoRenew { index: u128, enabled: u128 },
+
+    /// Exit the calling orbital's position in emergency mode, the principal is returned
+    /// or recorded for redemption without any penalty
+    #[opcode(64)]
+    EmergencyWithdraw { index: u128 },
+
+    /// Reset the tracked header chain to the header in the witness at `height`
+    #[opcode(70)]
+    SetHeaderCheckpoint { height: u128 },
+
+    /// Extend the tracked header chain with the 80-byte headers in the witness
+    #[opcode(71)]
+    SubmitHeaders,
+
+    /// Set the script BRC-20 deposits must be sent to, read from the witness
+    #[opcode(72)]
+    SetDepositScript,
+
+    /// Replace the lock-period weight table with the JSON table in the witness,
+    /// existing stakes keep the weight they were registered with
+    #[opcode(76)]
+    SetPeriodWeights,
+
+    /// Register a BRC-20 ticker from the JSON in the witness
+    #[opcode(77)]
+    AddBrc20Ticker,
+
+    /// Mark the redemption of an unstaked orbital as paid out, the witness carries the
+    /// 32-byte txid of the BRC-20 transfer
+    #[opcode(78)]
+    FulfillRedemption { index: u128 },
+
+    /// Withdraw the coins accrued to the treasury, authenticated with the treasury token
+    #[opcode(79)]
+    WithdrawTreasury,
+
+    /// Replace the alkane tokens accepted by StakeToken with the JSON list in the witness,
+    /// existing stakes keep the weight they were registered with
+    #[opcode(80)]
+    SetTokenWhitelist,
+
+    /// Set the guardian token allowed to pause the pool, [0, 0] removes it
+    #[opcode(81)]
+    SetGuardian { block: u128, tx: u128 },
+
+    /// Pause (non-zero) or resume a user opcode, the guardian can only pause
+    #[opcode(82)]
+    SetPaused { opcode: u128, paused: u128 },
+
+    /// Turn emergency mode on (non-zero) or off, the guardian can only turn it on.
+    /// All user opcodes are halted except EmergencyWithdraw
+    #[opcode(83)]
+    SetEmergency { enabled: u128 },
+
+    /// Rewrite up to `count` staking records in the current storage format, the schema
+    /// version is bumped once every record has been migrated
+    #[opcode(84)]
+    MigrateStorage { count: u128 },
+
+    /// Move the rounding dust recorded when positions done mining claim to the treasury
+    /// balance, only after mining has ended
+    #[opcode(85)]
+    SweepDust,
+
+    /// Attest the BRC-20 transfer inscriptions revealed by the wtxids in the witness
+    /// were backed by the inscriber's available balance, approved like a staking
+    /// registration
+    #[opcode(86)]
+    AttestDeposits,
+
+    /// Add an operator token allowed to approve staking registrations
+    #[opcode(73)]
+    AddOperator { block: u128, tx: u128 },
+
+    /// Remove an operator token
+    #[opcode(74)]
+    RemoveOperator { block: u128, tx: u128 },
+
+    /// Set how many operators must approve a staking registration
+    #[opcode(75)]
+    SetOperatorThreshold { threshold: u128 },
+
     /// Get the name of the collection
     #[opcode(99)]
     #[returns(String)]
@@ -149,6 +283,78 @@ enum StakingPoolMessage {
     #[returns(String)]
     GetBalance,
 
+    /// Get the tracked header tip as "height:blockhash"
+    #[opcode(1005)]
+    #[returns(String)]
+    GetHeaderTip,
+
+    /// Get the operator tokens and approval threshold as JSON
+    #[opcode(1006)]
+    #[returns(String)]
+    GetOperators,
+
+    /// Get the pool config as JSON
+    #[opcode(1007)]
+    #[returns(String)]
+    GetConfig,
+
+    /// Get the lock-period weight table as JSON
+    #[opcode(1008)]
+    #[returns(String)]
+    GetPeriodWeights,
+
+    /// Get the indexes of the orbitals directly invited by `index`
+    #[opcode(1009)]
+    #[returns(String)]
+    GetInvitees { index: u128 },
+
+    /// Get the referral rewards of `index` as ["earned","claimed"], they are credited
+    /// when the invitees claim so `height` is ignored
+    #[opcode(1010)]
+    #[returns(String)]
+    GetReferralEarnings { index: u128, height: u128 },
+
+    /// Get the registered BRC-20 tickers as JSON
+    #[opcode(1011)]
+    #[returns(String)]
+    GetBrc20Tickers,
+
+    /// Get the redemption record of an unstaked orbital as JSON
+    #[opcode(1012)]
+    #[returns(String)]
+    GetRedemption { index: u128 },
+
+    /// Get the indexes of the redemptions not yet fulfilled
+    #[opcode(1013)]
+    #[returns(String)]
+    GetPendingRedemptions,
+
+    /// Get the forfeited rewards burned and accrued to the treasury as JSON
+    #[opcode(1014)]
+    #[returns(String)]
+    GetPenaltyStats,
+
+    /// Get the alkane tokens accepted by StakeToken as JSON
+    #[opcode(1015)]
+    #[returns(String)]
+    GetTokenWhitelist,
+
+    /// Get the paused opcodes, emergency mode and guardian token as JSON
+    #[opcode(1016)]
+    #[returns(String)]
+    GetPauseState,
+
+    /// Get the storage schema version and the migration progress as JSON
+    #[opcode(1017)]
+    #[returns(String)]
+    GetSchemaVersion,
+
+    /// Get the emitted rewards, the rewards allocated to positions when they last claimed,
+    /// the claimed rewards and the rounding dust of positions done mining as JSON
+    #[opcode(1018)]
+    #[returns(String)]
+    GetRewardStats,
+
 }
 
 /// Implementation of Token trait
@@ -181,16 +387,52 @@ pub fn encode_string_to_u128(s: &str) -> (u128, u128) {
     (u1, u2)
 }
 
-pub fn period_to_w(period: u16) -> Decimal {
-    match period {
-        30 => Decimal::from_str("1.0").unwrap(),
-        90 => Decimal::from_str("1.5").unwrap(),
-        180 => Decimal::from_str("1.8").unwrap(),
-        360 => Decimal::from_str("2.2").unwrap(),
-        _ => Decimal::from_str("1.0").unwrap(),
+//收益保留到 coin 的最小单位，先舍去累加产生的精度误差
+fn to_coin(v: Decimal) -> Result<u128> {
+    Ok(v.floor().try_into()?)
+}
+
+//向下取整的 value·a/b，brc20 数量按 1e18 缩放，可能超出 Decimal 的范围
+fn mul_div(value: u128, a: u128, b: u128) -> Result<u128> {
+    if b == 0 {
+        return Err(anyhow!("division by zero"));
+    }
+    (value / b)
+        .checked_mul(a)
+        .zip(((value % b).checked_mul(a)).map(|v| v / b))
+        .and_then(|(q, r)| q.checked_add(r))
+        .ok_or_else(|| anyhow!("amount overflow"))
+}
+
+//向下取整的 value·rate，rate 不能为负
+fn mul_rate(value: u128, rate: Decimal) -> Result<u128> {
+    if rate.is_sign_negative() {
+        return Err(anyhow!("negative rate"));
+    }
+    let rate = rate.normalize();
+    mul_div(value, rate.mantissa() as u128, 10u128.pow(rate.scale()))
+}
+
+fn alkane_ids_to_vec(ids: &Vec<AlkaneId>) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(ids.len() * 32);
+    for id in ids {
+        bytes.extend_from_slice(&id.block.to_le_bytes());
+        bytes.extend_from_slice(&id.tx.to_le_bytes());
     }
+    bytes
+}
+
+fn alkane_ids_from_vec(bytes: &[u8]) -> Vec<AlkaneId> {
+    bytes
+        .chunks_exact(32)
+        .map(|chunk| AlkaneId {
+            block: u128::from_le_bytes(chunk[0..16].try_into().unwrap()),
+            tx: u128::from_le_bytes(chunk[16..32].try_into().unwrap()),
+        })
+        .collect()
 }
 
+
 impl StakingPool {
     /// Initialize the contract
     ///
@@ -201,12 +443,16 @@ impl StakingPool {
     fn initialize(&self) -> Result<CallResponse> {
         self.observe_initialization()?;
 
+        let config = PoolConfig::from_vec8(&self.witness_payload()?)?;
+        self.config_pointer().set(Arc::new(config.to_vec8()?));
+        self.schema_version_pointer().set_value::<u32>(SCHEMA_VERSION);
+
         self.add_brc20_name(BRC20_NAME_0);
 
         let context = self.context()?;
         let mut response = CallResponse::forward(&context.incoming_alkanes);
 
-        //部署coin合约，所有coin属于质押池合约
+        //部署coin合约，质押池持有 coin 的 auth token，发放收益时增发
         self.deploy_coin_token()?;
 
         // Collection token acts as auth token for contract minting without any limits
@@ -219,14 +465,15 @@ impl StakingPool {
     }
 
     fn deploy_coin_token(&self) -> Result<AlkaneTransfer> {
-        let (name_part1,name_part2) = encode_string_to_u128(COIN_NAME);
-        let (symbol,_) = encode_string_to_u128(COIN_SYMBOL);
+        let config = self.config();
+        let (name_part1,name_part2) = encode_string_to_u128(&config.coin_name);
+        let (symbol,_) = encode_string_to_u128(&config.coin_symbol);
         let cellpack = Cellpack {
             target: AlkaneId {
                 block: 5,
-                tx: COIN_TEMPLATE_ID,
+                tx: config.coin_template_id,
             },
-            inputs: vec![0x0, CAP,name_part1,name_part2,symbol],
+            inputs: vec![0x0, config.cap,name_part1,name_part2,symbol,1],
         };
 
         let sequence = self.sequence();
@@ -239,30 +486,203 @@ impl StakingPool {
 
         self.set_coin_id(&coin_id);
 
-        if response.alkanes.0.len() < 1 {
-            Err(anyhow!("orbital token not returned with factory"))
-        } else {
-            Ok(response.alkanes.0[0])
+        let Some(auth) = response.alkanes.0.iter().find(|transfer| transfer.id != coin_id) else {
+            return Err(anyhow!("coin auth token not returned with factory"));
+        };
+        self.set_coin_auth_id(&auth.id);
+        Ok(*auth)
+    }
+
+    //发放 coin，余额不足的部分用 auth token 增发
+    fn pay_coins(&self, value: u128) -> Result<AlkaneTransfer> {
+        let coin_id = self.get_coin_id();
+        let balance = self.balance(&self.context()?.myself, &coin_id);
+        if balance < value {
+            self.mint_coins(value - balance)?;
         }
+        Ok(AlkaneTransfer {
+            id: coin_id,
+            value,
+        })
+    }
+
+    fn mint_coins(&self, value: u128) -> Result<()> {
+        //旧版本的池在初始化时已经持有全部 coin
+        let Some(auth_id) = self.get_coin_auth_id() else {
+            return Err(anyhow!("coin supply is not owned by the pool"));
+        };
+        let cellpack = Cellpack {
+            target: self.get_coin_id(),
+            inputs: vec![78, value],
+        };
+        let parcel = AlkaneTransferParcel(vec![AlkaneTransfer {
+            id: auth_id,
+            value: 1,
+        }]);
+        self.call(&cellpack, &parcel, self.fuel())?;
+        Ok(())
     }
     
+    /// Staking registration approved by the operators.
+    ///
+    /// Every operator token sent along counts as one approval. Approvals for the same
+    /// payload are collected across calls until the threshold is reached, without
+    /// operators configured the auth token alone is enough.
     fn staking(&self) -> Result<CallResponse> {
+        self.check_paused(50)?;
+        let context = self.context()?;
 
-        self.only_owner()?;
-
-        let Ok(mut staking) = Staking::from_tx(self.transaction()) else {
+        let Ok(staking) = Staking::from_tx(self.transaction()) else {
             return Err(anyhow!("invalid staking transaction"));
         };
+        if staking.is_alkane_staking() {
+            return Err(anyhow!("unknown brc20 index"));
+        }
+
+        let key = sha256::Hash::hash(&self.witness_payload()?).to_byte_array().to_vec();
+        let mut registered = self.staking_registered_pointer(&key);
+        if registered.get_value::<u8>() == 1 {
+            return Err(anyhow!("staking already registered"));
+        }
+        //同一笔存入不能再用证明质押
+        let mut used = self.deposit_used_pointer(&staking.tx);
+        if used.get_value::<u8>() == 1 {
+            return Err(anyhow!("deposit already staked"));
+        }
+
+        if !self.approve_staking(&key)? {
+            return Ok(CallResponse::forward(&context.incoming_alkanes));
+        }
+        registered.set_value::<u8>(1);
+        used.set_value::<u8>(1);
+
+        self.register_staking(staking)
+    }
+
+    /// Records the approvals carried by this call, true once the threshold is reached
+    fn approve_staking(&self, key: &Vec<u8>) -> Result<bool> {
+        let operators = self.operator_list();
+        if operators.len() == 0 {
+            self.only_owner()?;
+            return Ok(true);
+        }
+
+        let context = self.context()?;
+        let mut approvals = self.get_staking_approvals(key);
+        //被移除的运营方之前的审批不再计数
+        approvals.retain(|id| operators.contains(id));
+        let mut approved = false;
+        for transfer in context.incoming_alkanes.0.iter() {
+            if transfer.value < 1 || !operators.contains(&transfer.id) {
+                continue;
+            }
+            approved = true;
+            if !approvals.contains(&transfer.id) {
+                approvals.push(transfer.id.clone());
+            }
+        }
+        if !approved {
+            return Err(anyhow!("did not authenticate with an operator token"));
+        }
+
+        if approvals.len() as u128 >= self.get_operator_threshold() {
+            self.staking_approvals_pointer(key).set(Arc::new(vec![]));
+            Ok(true)
+        } else {
+            self.staking_approvals_pointer(key).set(Arc::new(alkane_ids_to_vec(&approvals)));
+            Ok(false)
+        }
+    }
+
+    /// Staking without the auth token: the witness carries a proof that the BRC-20 was
+    /// sent to the deposit script in a block of the tracked header chain.
+    ///
+    /// The chain can't tell whether the inscriber had the balance for the transfer, so
+    /// the inscription must also have been attested by the operators. The proof is
+    /// public, so the claiming transaction must also spend an output of the deposit
+    /// transaction besides the deposit itself, which only the depositor controls.
+    fn staking_with_proof(&self) -> Result<CallResponse> {
+        self.check_paused(55)?;
+        let Ok(proof) = DepositProof::from_tx(self.transaction()) else {
+            return Err(anyhow!("invalid deposit proof"));
+        };
+        let deposit = self.verify_deposit(&proof)?;
+
+        let staking = Staking {
+            brc20_index: proof.brc20_index,
+            brc20_value: deposit.amount,
+            staking_value: deposit.amount / 10u128.pow(BRC20_DECIMALS),
+            period: proof.period,
+            tx: deposit.txid,
+            invite_index: 0,
+            staking_height: proof.height,
+            unstaking_height: 0,
+            alkanes_id: proof.invite_alkanes_id,
+            withdraw_coin_value: 0,
+            token_id: [0, 0],
+            auto_renew: false,
+            renewals: 0,
+        };
+        self.register_staking(staking)
+    }
+
+    //校验存入证明并标记为已使用
+    fn verify_deposit(&self, proof: &DepositProof) -> Result<Brc20Deposit> {
+        let Some(header) = self.get_header(proof.height) else {
+            return Err(anyhow!("block header not tracked"));
+        };
+        if self.header_tip() + 1 < proof.height + MIN_PROOF_CONFIRMATIONS {
+            return Err(anyhow!("deposit not confirmed"));
+        }
+
+        let deposit_script = self.deposit_script_pointer().get();
+        if deposit_script.len() == 0 {
+            return Err(anyhow!("deposit script not set"));
+        }
+        let deposit = proof.verify(&header, Script::from_bytes(&deposit_script))?;
+
+        if proof.brc20_index >= self.get_brc20_count() {
+            return Err(anyhow!("unknown brc20 index"));
+        }
+        if !deposit.tick.eq_ignore_ascii_case(&self.get_brc20_name(proof.brc20_index)) {
+            return Err(anyhow!("brc20 ticker mismatch"));
+        }
+        if self.deposit_attested_pointer(&deposit.inscription).get_value::<u8>() != 1 {
+            return Err(anyhow!("inscription not attested"));
+        }
+        //证明是公开的，只有能花费存入交易其他输出的存入者才能领取
+        let tx = consensus_decode::<Transaction>(&mut Cursor::new(self.transaction()))?;
+        if !tx.input.iter().any(|input| {
+            input.previous_output.txid.to_byte_array() == deposit.txid && input.previous_output.vout != 0
+        }) {
+            return Err(anyhow!("claim does not spend the depositor's output of the deposit"));
+        }
+
+        let mut used = self.deposit_used_pointer(&deposit.txid);
+        if used.get_value::<u8>() == 1 {
+            return Err(anyhow!("deposit already staked"));
+        }
+        used.set_value::<u8>(1);
+        Ok(deposit)
+    }
 
+    /// Validate a staking record and mint its orbital
+    fn register_staking(&self, mut staking: Staking) -> Result<CallResponse> {
+        let config = self.config();
         // TODO staking_height  小于当前高度，且是严格递增
-        if staking.staking_height < MINING_FIRST_HEIGHT{
+        if staking.staking_height < config.mining_first_height{
             return Err(anyhow!("Not yet started"));
-        }else if staking.staking_height > MINING_LAST_HEIGHT{
+        }else if staking.staking_height > config.mining_last_height{
             return Err(anyhow!("Mining ended"));
         }
-        if staking.staking_value < MIN_STAKING_VALUE as u128{
+        if staking.staking_value < config.min_staking_value{
             return Err(anyhow!("Not enough value"));
         }
+        let Some(period_w) = config.period_weight(staking.period) else {
+            return Err(anyhow!("unsupported staking period"));
+        };
+        let ratio = self.staking_ratio(&staking, &config)?;
+        let staking_w = period_w * ratio;
 
         let index = self.get_orbital_count().checked_add(1).unwrap();
 
@@ -272,121 +692,456 @@ impl StakingPool {
         let context = self.context()?;
         let mut response = CallResponse::forward(&context.incoming_alkanes);
 
+        let orbital = self.mint_orbital(index)?;
+        staking.alkanes_id = [orbital.id.block,orbital.id.tx];
+
+        self.add_staking(index,&staking,staking_w,self.height_to_no(self.height()));
+        self.staking_ratio_pointer(index).set(Arc::new(Staking::serialize_decimal(&ratio)?));
+
+        response.alkanes.0.push(orbital);
+        Ok(response)
+    }
+
+    fn mint_orbital(&self, index: u128) -> Result<AlkaneTransfer> {
         let cellpack = Cellpack {
             target: AlkaneId {
                 block: 5,
-                tx: ORBITAL_TEMPLATE_ID,
+                tx: self.config().orbital_template_id,
             },
             inputs: vec![0x0, index],
         };
-        let sequence = self.sequence();
         let subresponse = self.call(&cellpack, &AlkaneTransferParcel::default(), self.fuel())?;
-        staking.alkanes_id = [2,sequence];
+        if subresponse.alkanes.0.len() < 1 {
+            return Err(anyhow!("orbital token not returned with factory"));
+        }
+        Ok(subresponse.alkanes.0[0].clone())
+    }
 
-        self.add_staking(index,&staking);
+    //coin 按复投权重，白名单代币按各自比例，brc20 按 ticker 比例
+    fn staking_ratio(&self, staking: &Staking, config: &PoolConfig) -> Result<Decimal> {
+        if staking.is_alkane_staking() {
+            let token_id = staking.get_token_id();
+            if token_id == self.get_coin_id() {
+                return Ok(config.compound_weight);
+            }
+            return self
+                .token_whitelist()
+                .iter()
+                .find(|token| token.id == staking.token_id)
+                .map(|token| token.ratio)
+                .ok_or_else(|| anyhow!("token not whitelisted"));
+        }
+        if staking.brc20_index >= self.get_brc20_count() {
+            return Err(anyhow!("unknown brc20 index"));
+        }
+        Ok(self.get_brc20_ticker(staking.brc20_index).ratio)
+    }
 
-        if subresponse.alkanes.0.len() < 1 {
-            Err(anyhow!("orbital token not returned with factory"))
-        } else {
-            response.alkanes.0.push(subresponse.alkanes.0[0].clone());
-            Ok(response)
+    /// 复投：池托管转入的 coin 并按 coin 数量质押
+    fn compound(&self, period: u128) -> Result<CallResponse> {
+        self.check_paused(57)?;
+        self.stake_alkane(&self.get_coin_id(), period)
+    }
+
+    fn stake_token(&self, period: u128) -> Result<CallResponse> {
+        self.check_paused(58)?;
+        let context = self.context()?;
+        let whitelist = self.token_whitelist();
+        let Some(transfer) = context
+            .incoming_alkanes
+            .0
+            .iter()
+            .find(|transfer| whitelist.iter().any(|token| token.id == [transfer.id.block, transfer.id.tx]))
+        else {
+            return Err(anyhow!("no whitelisted token supplied"));
+        };
+        self.stake_alkane(&transfer.id, period)
+    }
+
+    /// 池托管转入的 `token_id` 并按数量质押，其他 alkane 原样退回
+    fn stake_alkane(&self, token_id: &AlkaneId, period: u128) -> Result<CallResponse> {
+        let context = self.context()?;
+        let value: u128 = context
+            .incoming_alkanes
+            .0
+            .iter()
+            .filter(|transfer| transfer.id == *token_id)
+            .try_fold(0u128, |acc, transfer| acc.checked_add(transfer.value))
+            .ok_or_else(|| anyhow!("token value overflow"))?;
+        if value == 0 {
+            return Err(anyhow!("no token supplied to stake"));
         }
-    
+        let Ok(period) = u16::try_from(period) else {
+            return Err(anyhow!("unsupported staking period"));
+        };
+
+        let tx = consensus_decode::<Transaction>(&mut Cursor::new(self.transaction()))?;
+        let staking = Staking {
+            brc20_index: ALKANE_STAKING_INDEX,
+            brc20_value: value,
+            staking_value: value,
+            period,
+            tx: tx.compute_txid().to_byte_array(),
+            invite_index: 0,
+            staking_height: self.height(),
+            unstaking_height: 0,
+            alkanes_id: [0, 0],
+            withdraw_coin_value: 0,
+            token_id: [token_id.block, token_id.tx],
+            auto_renew: false,
+            renewals: 0,
+        };
+        let mut response = self.register_staking(staking)?;
+        response.alkanes.0.retain(|transfer| transfer.id != *token_id);
+        Ok(response)
+    }
+
+    fn set_token_whitelist(&self) -> Result<CallResponse> {
+        self.only_owner()?;
+        let context = self.context()?;
+
+        let tokens: Vec<StakeToken> = serde_json::from_slice(&self.witness_payload()?)
+            .map_err(|e| anyhow!("invalid token whitelist: {}", e))?;
+        validate_token_whitelist(&tokens)?;
+        let coin_id = self.get_coin_id();
+        if tokens.iter().any(|token| token.id == [coin_id.block, coin_id.tx]) {
+            return Err(anyhow!("coin is staked with Compound"));
+        }
+        self.token_whitelist_pointer().set(Arc::new(serde_json::to_vec(&tokens)?));
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    /// Reset the tracked header chain to a trusted checkpoint
+    fn set_header_checkpoint(&self, height: u128) -> Result<CallResponse> {
+        self.only_owner()?;
+        let context = self.context()?;
+
+        let header = consensus_decode::<Header>(&mut Cursor::new(self.witness_payload()?))
+            .map_err(|_| anyhow!("invalid block header"))?;
+        self.set_header(height as u64, &header);
+        self.header_tip_pointer().set_value::<u64>(height as u64);
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    /// Anyone can extend the tracked chain with headers carrying valid proof of work.
+    ///
+    /// The headers may fork off one of the last MAX_REORG_DEPTH tracked headers, the
+    /// branch then replaces the tracked one if it carries more work.
+    fn submit_headers(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+
+        let data = self.witness_payload()?;
+        if data.len() == 0 || data.len() % 80 != 0 {
+            return Err(anyhow!("witness is not a list of block headers"));
+        }
+        let mut headers = Vec::with_capacity(data.len() / 80);
+        for chunk in data.chunks(80) {
+            headers.push(consensus_decode::<Header>(&mut Cursor::new(chunk.to_vec()))?);
+        }
+
+        let tip = self.header_tip();
+        if self.get_header(tip).is_none() {
+            return Err(anyhow!("header checkpoint not set"));
+        }
+        let Some((fork, mut parent)) = (tip.saturating_sub(MAX_REORG_DEPTH)..=tip)
+            .rev()
+            .filter_map(|height| self.get_header(height).map(|header| (height, header)))
+            .find(|(_, header)| header.block_hash() == headers[0].prev_blockhash)
+        else {
+            return Err(anyhow!("header does not extend the tracked chain"));
+        };
+
+        //分叉时新分支的累计工作量必须超过被替换的区块头
+        let replaced = (fork + 1..=tip)
+            .filter_map(|height| self.get_header(height))
+            .map(|header| header.work())
+            .reduce(|a, b| a + b);
+        if let Some(replaced) = replaced {
+            let work = headers.iter().map(|header| header.work()).reduce(|a, b| a + b);
+            if work.map_or(true, |work| work <= replaced) {
+                return Err(anyhow!("branch has less work than the tracked chain"));
+            }
+        }
+
+        let mut height = fork;
+        for header in headers.iter() {
+            height += 1;
+            validate_next_header(&parent, height, header)?;
+            self.set_header(height, header);
+            parent = *header;
+        }
+        for stale in height + 1..=tip {
+            self.header_pointer(stale).set(Arc::new(vec![]));
+        }
+        self.header_tip_pointer().set_value::<u64>(height);
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    fn attest_deposits(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+
+        let data = self.witness_payload()?;
+        if data.len() == 0 || data.len() % 32 != 0 {
+            return Err(anyhow!("witness is not a list of wtxids"));
+        }
+        for wtxid in data.chunks(32) {
+            //与质押登记的审批分开计数
+            let key = sha256::Hash::hash(&[b"attest".as_slice(), wtxid].concat())
+                .to_byte_array()
+                .to_vec();
+            if self.approve_staking(&key)? {
+                self.deposit_attested_pointer(wtxid).set_value::<u8>(1);
+            }
+        }
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    fn set_deposit_script(&self) -> Result<CallResponse> {
+        self.only_owner()?;
+        let context = self.context()?;
+
+        let script = self.witness_payload()?;
+        if script.len() == 0 {
+            return Err(anyhow!("empty deposit script"));
+        }
+        self.deposit_script_pointer().set(Arc::new(script));
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    fn set_period_weights(&self) -> Result<CallResponse> {
+        self.only_owner()?;
+        let context = self.context()?;
+
+        let weights: Vec<PeriodWeight> = serde_json::from_slice(&self.witness_payload()?)
+            .map_err(|e| anyhow!("invalid period table: {}", e))?;
+        let mut config = self.config();
+        config.period_weights = weights;
+        config.validate()?;
+        self.config_pointer().set(Arc::new(config.to_vec8()?));
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    fn add_brc20_ticker(&self) -> Result<CallResponse> {
+        self.only_owner()?;
+        let context = self.context()?;
+
+        let ticker = Brc20Ticker::from_vec8(&self.witness_payload()?)?;
+        let count = self.get_brc20_count();
+        //最后一个索引保留给 alkane 质押
+        if count == ALKANE_STAKING_INDEX {
+            return Err(anyhow!("too many brc20 tickers"));
+        }
+        let tickers: Vec<Brc20Ticker> = (0..count).map(|i| self.get_brc20_ticker(i)).collect();
+        if tickers.iter().any(|t| t.name.eq_ignore_ascii_case(&ticker.name)) {
+            return Err(anyhow!("brc20 ticker already registered"));
+        }
+
+        //独立排放总量加上推荐奖励不能超过 coin 总量
+        let config = self.config();
+        let days = config.mining_days() as u128;
+        let emission = tickers
+            .iter()
+            .chain(std::iter::once(&ticker))
+            .try_fold(0u128, |acc, t| {
+                t.day_volume.checked_mul(days).and_then(|v| v.checked_add(acc))
+            })
+            .ok_or_else(|| anyhow!("emission overflow"))?;
+        let committed = config
+            .with_referral(emission)?
+            .checked_add(config.mining_cap)
+            .ok_or_else(|| anyhow!("emission overflow"))?;
+        if committed > config.cap {
+            return Err(anyhow!("brc20 emission exceeds cap"));
+        }
+
+        self.brc20_ticker_pointer().select_index(count as u32).set(Arc::new(ticker.to_vec8()?));
+        self.add_brc20_name(&ticker.name);
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    fn add_operator(&self, block: u128, tx: u128) -> Result<CallResponse> {
+        self.only_owner()?;
+        let context = self.context()?;
+
+        let id = AlkaneId { block, tx };
+        let mut operators = self.operator_list();
+        if operators.contains(&id) {
+            return Err(anyhow!("operator already added"));
+        }
+        operators.push(id);
+        if operators.len() == 1 {
+            self.operator_threshold_pointer().set_value::<u128>(1);
+        }
+        self.set_operators(&operators);
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    fn remove_operator(&self, block: u128, tx: u128) -> Result<CallResponse> {
+        self.only_owner()?;
+        let context = self.context()?;
+
+        let id = AlkaneId { block, tx };
+        let mut operators = self.operator_list();
+        let Some(position) = operators.iter().position(|operator| *operator == id) else {
+            return Err(anyhow!("not an operator"));
+        };
+        operators.remove(position);
+        if operators.len() > 0 && (operators.len() as u128) < self.get_operator_threshold() {
+            return Err(anyhow!("threshold exceeds the remaining operators"));
+        }
+        self.set_operators(&operators);
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    fn set_operator_threshold(&self, threshold: u128) -> Result<CallResponse> {
+        self.only_owner()?;
+        let context = self.context()?;
+
+        if threshold < 1 || threshold > self.operator_list().len() as u128 {
+            return Err(anyhow!("threshold must be between 1 and the number of operators"));
+        }
+        self.operator_threshold_pointer().set_value::<u128>(threshold);
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    fn witness_payload(&self) -> Result<Vec<u8>> {
+        let tx = consensus_decode::<Transaction>(&mut Cursor::new(self.transaction()))?;
+        Ok(find_witness_payload(&tx, 0).unwrap_or_else(|| vec![]))
     }
 
 
     //不依赖中间状态的算法，两种可以对比验证
     fn calc_profit_1(&self,index:u128,height:u128) -> Result<(u128,u128,u128)>{ 
+        let config = self.config();
         let count  = self.get_orbital_count();
         let curr_staking = self.get_staking(index);
-        let start = self.height_to_no(curr_staking.staking_height);
-        let end: u64 = self.height_to_no(curr_staking.get_mining_end_height(height as u64));
-        let  c  = Decimal::from(curr_staking.staking_value)
-        .checked_mul(Decimal::from(end-start)).unwrap()
-        .checked_mul(Decimal::from(period_to_w(curr_staking.period))).unwrap();
+        let pool = self.weight_pool(curr_staking.brc20_index);
+        let start = self.get_staking_start_day(index,&curr_staking,&config);
+        let end: u64 = max(start,config.height_to_no(curr_staking.get_mining_end_height(height as u64)));
 
         let mut pre_v =vec![Decimal::from(0);(end-start) as usize];
 
-        let mut v = Decimal::from(0);
         for i in 0..count{
+            if self.staking_pointer(i+1).get().len() == 0 {
+                continue;
+            }
             let staking = self.get_staking(i+1);
-            let t_s = self.height_to_no(staking.staking_height);
-            let t_e = self.height_to_no(staking.get_mining_end_height( height as u64));
-            let length = max(min(t_e,end)-max(t_s,start),0);
-            if length == 0{
+            if self.weight_pool(staking.brc20_index) != pool {
+                continue;
+            }
+            let segments = self.get_weight_segments(i+1,&staking,&config);
+            let t_s = self.get_staking_start_day(i+1,&staking,&config);
+            let t_e = config.height_to_no(staking.get_mining_end_height( height as u64));
+            if min(t_e,end) <= max(t_s,start) {
                 continue;
             }
-            v = v.checked_add(period_to_w(staking.period).checked_mul(Decimal::from(staking.staking_value.checked_mul(length as u128).unwrap())).unwrap()).unwrap() ;
-
             let mut cross_s = max(t_s,start);
             let cross_e = min(t_e,end);
 
             //计算每个快质押量
             while cross_s < cross_e {
                 let t = (cross_s -start) as usize;
-                pre_v[t] = pre_v[t].checked_add(period_to_w(staking.period).checked_mul(Decimal::from(staking.staking_value)).unwrap()).unwrap();
+                pre_v[t] = pre_v[t].checked_add(WeightSegment::weight_on(&segments,cross_s)).unwrap();
                 cross_s +=1;
             }
         }
-        let p = if v > Decimal::from(0) {
-            c.checked_div(v).unwrap().checked_mul(Decimal::from(MINING_ONE_DAY_VOLUME)).unwrap().checked_mul(Decimal::from(end-start)).unwrap()
-        }else{
-            Decimal::from(0)
-        };
-
-        let curr_staking_w = Decimal::from(curr_staking.staking_value).checked_mul(period_to_w(curr_staking.period)).unwrap();
-        //计算每个快收益
-        pre_v.iter_mut().for_each(|v| *v = curr_staking_w.checked_div(*v).unwrap().checked_mul(Decimal::from(MINING_ONE_DAY_VOLUME)).unwrap());
+        let curr_segments = self.get_weight_segments(index,&curr_staking,&config);
+        //计算每个快收益，每天排放量可能不同
+        pre_v.iter_mut().enumerate().for_each(|(i,v)| {
+            let day = start + i as u64;
+            *v = WeightSegment::weight_on(&curr_segments,day).checked_div(*v).unwrap().checked_mul(Decimal::from(self.pool_day_volume(pool,day,&config))).unwrap()
+        });
+        let p: Decimal = pre_v.iter().sum();
 
-        let release_end = self.height_to_no(curr_staking.get_release_end_height(height as u64));
+        let release_end = config.height_to_no(curr_staking.get_release_end_height(height as u64));
         //计算释放收益
-        let rate = Decimal::from(1) / Decimal::from(PROFIT_RELEASE_DAY);
+        let rate = Decimal::from(1) / Decimal::from(config.profit_release_day);
         let release_p: Decimal = pre_v.iter().enumerate().map(|(i,v)| {
             let cnt = release_end.checked_sub(i as u64 + start +1).unwrap();
-            if cnt >= PROFIT_RELEASE_DAY {
+            if cnt >= config.profit_release_day {
                 *v
             } else {
                 v.checked_mul(rate).unwrap().checked_mul(Decimal::from(cnt)).unwrap()
             }
         }).sum();
     
-        return Ok((p.floor().try_into().unwrap(),release_p.floor().try_into().unwrap(),curr_staking.withdraw_coin_value));
+        let release_p = to_coin(release_p)? + self.get_early_release(index,&curr_staking,height as u64);
+        return Ok((to_coin(p)?,release_p,curr_staking.withdraw_coin_value));
     }
 
+    /// 挖矿收益 w·(A(e)-A(s))；第 d 天的收益在之后 T 天线性释放，
+    /// 未释放完的天 [m,e) 释放 w/T·Σ(R-1-d)·r_d = w/T·((R-1)·ΔA-ΔB)
+    ///
+    /// 追加或延长过的质押按权重分段求和
     fn calc_profit(&self,index:u128,height:u128) -> Result<(u128,u128,u128)>{
         let curr_staking = self.get_staking(index);
-        let mut start = self.height_to_no(curr_staking.staking_height);
-        let end = self.height_to_no(curr_staking.get_mining_end_height(height as u64));
-        let curr_staking_w = Decimal::from(curr_staking.staking_value) * period_to_w(curr_staking.period);
-        let rate =Decimal::from(1) / Decimal::from(PROFIT_RELEASE_DAY);
-        let factor = curr_staking_w * Decimal::from(MINING_ONE_DAY_VOLUME);
-        let release_end = self.height_to_no(curr_staking.get_release_end_height(height as u64));
-
-        let mut total_p = Decimal::from(0); 
-        let mut total_r = Decimal::from(0);
-        while start < end{ 
-            let p = factor / self.get_staking_weight(start);
+        let Some((total_p,total_r)) = self.calc_profit_exact(index,&curr_staking,height as u64) else {
+            return Ok((0,0,curr_staking.withdraw_coin_value));
+        };
+
+        Ok((to_coin(total_p)?,
+            to_coin(total_r)? + self.get_early_release(index,&curr_staking,height as u64),
+            curr_staking.withdraw_coin_value))
+    }
+
+    //没有取整的挖矿和释放收益，还没有挖矿的天时为 None
+    fn calc_profit_exact(&self,index:u128,curr_staking:&Staking,height:u64) -> Option<(Decimal,Decimal)>{
+        let config = self.config();
+        let pool = self.weight_pool(curr_staking.brc20_index);
+        let start = self.get_staking_start_day(index,curr_staking,&config);
+        let end = config.height_to_no(curr_staking.get_mining_end_height(height));
+        if end <= start {
+            return None;
+        }
+        let release_end = config.height_to_no(curr_staking.get_release_end_height(height));
+        let segments = self.get_weight_segments(index,curr_staking,&config);
+        let mut total_p = Decimal::ZERO;
+        let mut total_r = Decimal::ZERO;
+        for (i, segment) in segments.iter().enumerate() {
+            let s = max(segment.from_day,start);
+            let e = segments.get(i+1).map_or(end,|next| min(next.from_day,end));
+            if e <= s {
+                continue;
+            }
+            let (p,r) = self.segment_profit(pool,segment.weight,s,e,release_end,&config);
             total_p += p;
-            let cnt = release_end-start-1; //下个块开始释放
-            let r = if cnt >= PROFIT_RELEASE_DAY {
-                p
-            } else {
-                p * rate * Decimal::from(cnt)
-            };
             total_r += r;
-            start += 1;
         }
+        Some((total_p,total_r))
+    }
 
-        Ok((total_p.floor().try_into()?,
-            total_r.floor().try_into()?,
-            curr_staking.withdraw_coin_value))
+    /// 权重 `w` 在 [start,end) 挖出和到 `release_end` 释放的收益
+    fn segment_profit(&self,pool:u8,w:Decimal,start:u64,end:u64,release_end:u64,config:&PoolConfig) -> (Decimal,Decimal){
+        let release_day = config.profit_release_day;
+        let full_end = min(max(release_end.saturating_sub(release_day),start),end);
+
+        let acc_start = self.get_acc_at(pool,start,config);
+        let acc_full = self.get_acc_at(pool,full_end,config);
+        let acc_end = self.get_acc_at(pool,end,config);
+
+        let partial = Decimal::from(release_end - 1) * (acc_end.a - acc_full.a) - (acc_end.b - acc_full.b);
+        (w * (acc_end.a - acc_start.a),
+            w * (acc_full.a - acc_start.a) + w * partial / Decimal::from(release_day))
     }
 
+    /// [mined, released, withdrawn] followed by the same amounts formatted with the coin decimals
     fn get_profit(&self,index:u128,height:u128) ->Result<CallResponse> { 
         let (p,r,w) = self.calc_profit(index,height)?;
         let context = self.context()?;
         let mut response = CallResponse::forward(&context.incoming_alkanes);
-        response.data = serde_json::to_vec(&[p.to_string(),r.to_string(),w.to_string()]).unwrap();
+        let decimals = self.token_decimals(&self.get_coin_id());
+        response.data = serde_json::to_vec(&[p.to_string(),r.to_string(),w.to_string(),
+            format_amount(p,decimals),format_amount(r,decimals),format_amount(w,decimals)]).unwrap();
         Ok(response)
     }
 
@@ -394,7 +1149,27 @@ impl StakingPool {
         serde_json::to_vec(&[p.to_string(),r.to_string(),w.to_string()]).unwrap()
     }
 
-    fn unstaking(&self) -> Result<CallResponse> { 
+    /// 推荐奖励：被邀请人领取挖矿收益时，沿邀请链按每一级的比例记给邀请人，与挖矿收益分开领取
+    fn credit_referral(&self,index:u128,claimed:u128,config:&PoolConfig) -> Result<()>{
+        let mut invite_index = self.get_staking(index).invite_index;
+        let mut total = 0u128;
+        for rate in config.referral_rates.iter() {
+            if invite_index == 0 {
+                break;
+            }
+            let reward: u128 = (Decimal::from(claimed) * rate).floor().try_into()?;
+            let mut credited = self.referral_credited_pointer(invite_index);
+            credited.set_value::<u128>(credited.get_value::<u128>() + reward);
+            total += reward;
+            invite_index = self.get_staking(invite_index).invite_index;
+        }
+        let mut credited = self.referral_credited_total_pointer();
+        credited.set_value::<u128>(credited.get_value::<u128>() + total);
+        Ok(())
+    }
+
+    fn claim_referral(&self) -> Result<CallResponse> {
+        self.check_paused(56)?;
         let context = self.context()?;
 
         let caller_index = self.staking_id2index_pointer(&context.caller).get_value::<u128>();
@@ -402,12 +1177,744 @@ impl StakingPool {
             return Err(anyhow!("caller is not staking"));
         }
 
-        self.staking_unstaking(caller_index)?;
-        let response = CallResponse::forward(&context.incoming_alkanes);
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+
+        let earned = self.referral_credited_pointer(caller_index).get_value::<u128>();
+        let mut claimed = self.referral_claimed_pointer(caller_index);
+        let c = claimed.get_value::<u128>();
+        if earned > c {
+            response.alkanes.0.push(self.pay_coins(earned-c)?);
+            claimed.set_value::<u128>(earned);
+            let mut total = self.referral_claimed_total_pointer();
+            total.set_value::<u128>(total.get_value::<u128>() + earned-c);
+        }
+
         Ok(response)
     }
 
+    fn unstaking(&self) -> Result<CallResponse> { 
+        self.check_paused(51)?;
+        let context = self.context()?;
+
+        let caller_index = self.staking_id2index_pointer(&context.caller).get_value::<u128>();
+        if caller_index == 0 {
+            return Err(anyhow!("caller is not staking"));
+        }
+
+        self.staking_unstaking(caller_index)?;
+        let penalty_value = self.settle_early_unstake(caller_index)?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        let staking = self.get_staking(caller_index);
+        if staking.is_alkane_staking() {
+            response.alkanes.0.push(self.return_tokens(&staking.get_token_id(), staking.brc20_value - penalty_value)?);
+        } else {
+            self.add_redemption(caller_index, penalty_value)?;
+        }
+        Ok(response)
+    }
+
+    //退还质押的 alkane，复投的 coin 可能已经作为收益发放，需要时增发
+    fn return_tokens(&self, id: &AlkaneId, value: u128) -> Result<AlkaneTransfer> {
+        if *id == self.get_coin_id() {
+            return self.pay_coins(value);
+        }
+        Ok(AlkaneTransfer {
+            id: *id,
+            value,
+        })
+    }
+
+    //代理调用的 orbital 必须是 `index` 的质押
+    fn caller_position(&self, index: u128) -> Result<Staking> {
+        let context = self.context()?;
+        let caller_index = self.staking_id2index_pointer(&context.caller).get_value::<u128>();
+        if caller_index == 0 || caller_index != index {
+            return Err(anyhow!("caller is not staking"));
+        }
+        self.active_position(index)
+    }
+
+    fn active_position(&self, index: u128) -> Result<Staking> {
+        let staking = self.get_staking(index);
+        let today = self.height_to_no(self.height());
+        if staking.unstaking_height > 0 || self.height_to_no(staking.get_expire_height()) <= today {
+            return Err(anyhow!("position is not active"));
+        }
+        Ok(staking)
+    }
+
+    //增加权重只能在挖矿期间
+    fn check_mining(&self) -> Result<()> {
+        if self.height() > self.config().mining_last_height {
+            return Err(anyhow!("Mining ended"));
+        }
+        Ok(())
+    }
+
+    fn top_up(&self, index: u128) -> Result<CallResponse> {
+        self.check_paused(59)?;
+        let context = self.context()?;
+        let mut staking = self.caller_position(index)?;
+        self.check_mining()?;
+        if !staking.is_alkane_staking() {
+            return self.top_up_brc20(index, staking);
+        }
+        let token_id = staking.get_token_id();
+        let value: u128 = context
+            .incoming_alkanes
+            .0
+            .iter()
+            .filter(|transfer| transfer.id == token_id)
+            .try_fold(0u128, |acc, transfer| acc.checked_add(transfer.value))
+            .ok_or_else(|| anyhow!("token value overflow"))?;
+        if value == 0 {
+            return Err(anyhow!("no token supplied to top up"));
+        }
+
+        staking.staking_value = staking.staking_value.checked_add(value).ok_or_else(|| anyhow!("staking value overflow"))?;
+        staking.brc20_value = staking.brc20_value.checked_add(value).ok_or_else(|| anyhow!("token value overflow"))?;
+        let new_w = Decimal::from(staking.staking_value) * self.get_staking_w(index,&staking);
+        self.reweight_staking(index,new_w,self.expire_day(&staking),self.height_to_no(self.height()));
+        self.set_staking(index,&staking);
+
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        response.alkanes.0.retain(|transfer| transfer.id != token_id);
+        Ok(response)
+    }
+
+    /// brc20 质押用已证明的存入追加，证明中的周期和邀请人不使用
+    fn top_up_brc20(&self, index: u128, mut staking: Staking) -> Result<CallResponse> {
+        let context = self.context()?;
+        let Ok(proof) = DepositProof::from_tx(self.transaction()) else {
+            return Err(anyhow!("invalid deposit proof"));
+        };
+        if proof.brc20_index != staking.brc20_index {
+            return Err(anyhow!("brc20 ticker mismatch"));
+        }
+        let deposit = self.verify_deposit(&proof)?;
+        let value = deposit.amount / 10u128.pow(BRC20_DECIMALS);
+        if value == 0 {
+            return Err(anyhow!("no token supplied to top up"));
+        }
+
+        staking.staking_value = staking.staking_value.checked_add(value).ok_or_else(|| anyhow!("staking value overflow"))?;
+        staking.brc20_value = staking.brc20_value.checked_add(deposit.amount).ok_or_else(|| anyhow!("token value overflow"))?;
+        let new_w = Decimal::from(staking.staking_value) * self.get_staking_w(index,&staking);
+        self.reweight_staking(index,new_w,self.expire_day(&staking),self.height_to_no(self.height()));
+        self.set_staking(index,&staking);
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    /// 周期权重按当前权重表重新计算，资产比例保持登记时的值，到期高度从原质押高度起算
+    fn extend_period(&self, index: u128, period: u128) -> Result<CallResponse> {
+        self.check_paused(60)?;
+        let context = self.context()?;
+        let config = self.config();
+        let mut staking = self.caller_position(index)?;
+        self.check_mining()?;
+        let Some(period_w) = u16::try_from(period).ok().and_then(|p| config.period_weight(p)) else {
+            return Err(anyhow!("unsupported staking period"));
+        };
+        if period as u16 <= staking.period {
+            return Err(anyhow!("period must be longer than the current one"));
+        }
+
+        let staking_w = period_w * self.get_staking_ratio(index);
+        staking.period = period as u16;
+        let new_w = Decimal::from(staking.staking_value) * staking_w;
+        self.reweight_staking(index,new_w,self.expire_day(&staking),self.height_to_no(self.height()));
+        self.staking_w_pointer(index).set(Arc::new(Staking::serialize_decimal(&staking_w)?));
+        self.set_staking(index,&staking);
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    /// 按 `value` 拆出一个新的 orbital，权重历史、brc20 数量和已领取收益按比例分配
+    fn split(&self, index: u128, value: u128) -> Result<CallResponse> {
+        self.check_paused(61)?;
+        let context = self.context()?;
+        let config = self.config();
+        let mut staking = self.caller_position(index)?;
+        if value == 0 || value >= staking.staking_value {
+            return Err(anyhow!("split value must be less than the staked value"));
+        }
+        let rest = staking.staking_value - value;
+        if value < config.min_staking_value || rest < config.min_staking_value {
+            return Err(anyhow!("Not enough value"));
+        }
+
+        let f = Decimal::from(value) / Decimal::from(staking.staking_value);
+        let staking_w = self.get_staking_w(index,&staking);
+        let start = self.get_staking_start_day(index,&staking,&config);
+        let (mut split_segments, mut rest_segments) = WeightSegment::split_vec(&self.get_weight_segments(index,&staking,&config),f);
+        //当前权重保持 数量×权重，解质押和到期时扣除的权重不变
+        split_segments.last_mut().unwrap().weight = Decimal::from(value) * staking_w;
+        rest_segments.last_mut().unwrap().weight = Decimal::from(rest) * staking_w;
+
+        let mut split = staking.clone();
+        split.staking_value = value;
+        split.brc20_value = if staking.is_alkane_staking() {
+            value
+        } else {
+            mul_div(staking.brc20_value,value,staking.staking_value)?
+        };
+        split.withdraw_coin_value = mul_div(staking.withdraw_coin_value,value,staking.staking_value)?;
+        staking.staking_value = rest;
+        staking.brc20_value -= split.brc20_value;
+        staking.withdraw_coin_value -= split.withdraw_coin_value;
+
+        let split_index = self.get_orbital_count().checked_add(1).unwrap();
+        let orbital = self.mint_orbital(split_index)?;
+        split.alkanes_id = [orbital.id.block,orbital.id.tx];
+        self.set_staking(split_index,&split);
+        self.staking_id2index_pointer(&orbital.id).set_value(split_index);
+        self.index_invite(split_index,split.invite_index);
+        self.staking_w_pointer(split_index).set(Arc::new(Staking::serialize_decimal(&staking_w)?));
+        self.split_settlement(index,split_index,f)?;
+        self.staking_ratio_pointer(split_index).set(Arc::new(Staking::serialize_decimal(&self.get_staking_ratio(index))?));
+        self.staking_start_day_pointer(split_index).set_value::<u64>(start);
+        self.set_weight_segments(split_index,&split_segments);
+        self.set_orbital_count(split_index);
+
+        self.set_staking(index,&staking);
+        self.set_weight_segments(index,&rest_segments);
+
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        response.alkanes.0.push(orbital);
+        Ok(response)
+    }
+
+    /// 把一同转入的 orbital 的质押并入调用的 orbital，两者周期、到期日、资产和权重必须相同
+    ///
+    /// 转入的 orbital 留在质押池中，其邀请关系和推荐奖励一并转移
+    fn merge(&self, index: u128) -> Result<CallResponse> {
+        self.check_paused(62)?;
+        let context = self.context()?;
+        let mut staking = self.caller_position(index)?;
+        let Some(other_index) = context
+            .incoming_alkanes
+            .0
+            .iter()
+            .map(|transfer| self.staking_id2index_pointer(&transfer.id).get_value::<u128>())
+            .find(|i| *i != 0 && *i != index)
+        else {
+            return Err(anyhow!("no orbital supplied to merge"));
+        };
+        let mut other = self.active_position(other_index)?;
+        let config = self.config();
+        let height = self.height();
+        if other.period != staking.period
+            || other.auto_renew != staking.auto_renew
+            || config.height_to_no(self.lock_end_height(&other,height)) != config.height_to_no(self.lock_end_height(&staking,height))
+        {
+            return Err(anyhow!("positions must have the same period and expiry"));
+        }
+        if other.brc20_index != staking.brc20_index || other.token_id != staking.token_id {
+            return Err(anyhow!("positions must stake the same asset"));
+        }
+        if self.get_staking_w(other_index,&other) != self.get_staking_w(index,&staking) {
+            return Err(anyhow!("positions must have the same weight"));
+        }
+
+        let merged = WeightSegment::merge_vec(&self.get_weight_segments(index,&staking,&config),&self.get_weight_segments(other_index,&other,&config));
+        let start = min(self.get_staking_start_day(index,&staking,&config),self.get_staking_start_day(other_index,&other,&config));
+
+        staking.staking_value = staking.staking_value.checked_add(other.staking_value).ok_or_else(|| anyhow!("staking value overflow"))?;
+        staking.brc20_value = staking.brc20_value.checked_add(other.brc20_value).ok_or_else(|| anyhow!("token value overflow"))?;
+        staking.withdraw_coin_value = staking.withdraw_coin_value.checked_add(other.withdraw_coin_value).ok_or_else(|| anyhow!("coin value overflow"))?;
+        self.set_staking(index,&staking);
+        self.staking_start_day_pointer(index).set_value::<u64>(start);
+        self.set_weight_segments(index,&merged);
+
+        let other_orbital = other.get_alanes_id();
+        self.staking_id2index_pointer(&other_orbital).set_value::<u128>(0);
+        self.set_weight_segments(other_index,&vec![WeightSegment { from_day: start, weight: Decimal::ZERO }]);
+        other.staking_value = 0;
+        other.brc20_value = 0;
+        other.withdraw_coin_value = 0;
+        self.set_staking(other_index,&other);
+
+        let mut invitees = self.get_invite_indexs(index);
+        invitees.extend(self.get_invite_indexs(other_index));
+        self.staking_invite_pointer(index).set(Arc::new(Staking::serialize_invite_vec(&invitees)?));
+        self.staking_invite_pointer(other_index).set(Arc::new(vec![]));
+        for (mut merged, mut other) in [
+            (self.referral_claimed_pointer(index), self.referral_claimed_pointer(other_index)),
+            (self.referral_credited_pointer(index), self.referral_credited_pointer(other_index)),
+            (self.staking_allocated_pointer(index), self.staking_allocated_pointer(other_index)),
+        ] {
+            merged.set_value::<u128>(merged.get_value::<u128>() + other.get_value::<u128>());
+            other.set_value::<u128>(0);
+        }
+        let dust = self.get_staking_dust(index) + self.get_staking_dust(other_index);
+        self.staking_dust_pointer(index).set(Arc::new(Staking::serialize_decimal(&dust)?));
+        self.staking_dust_pointer(other_index).set(Arc::new(vec![]));
+
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        response.alkanes.0.retain(|transfer| transfer.id != other_orbital);
+        Ok(response)
+    }
+
+    fn set_auto_renew(&self, index: u128, enabled: u128) -> Result<CallResponse> {
+        self.check_paused(63)?;
+        let context = self.context()?;
+        self.caller_position(index)?;
+        self.renew_staking(index,enabled != 0,self.height())?;
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    /// `height` 时开启或关闭自动续期
+    ///
+    /// 开启时取消到期日扣除的权重；关闭时质押在当前周期结束时到期
+    fn renew_staking(&self, index: u128, enabled: bool, height: u64) -> Result<()> {
+        let mut staking = self.get_staking(index);
+        if staking.auto_renew == enabled {
+            return Ok(());
+        }
+
+        let config = self.config();
+        let pool = self.weight_pool(staking.brc20_index);
+        let w = self.get_weight_segments(index,&staking,&config).last().map_or(Decimal::ZERO,|segment| segment.weight);
+        if enabled {
+            self.add_weight_delta(pool,config.height_to_no(staking.get_expire_height()),w);
+            staking.auto_renew = true;
+        } else {
+            let end = staking.get_period_end_height(height);
+            let periods = (end - staking.staking_height) / (staking.period as u64 * 144);
+            staking.renewals = u32::try_from(periods - 1).map_err(|_| anyhow!("too many renewals"))?;
+            staking.auto_renew = false;
+            self.add_weight_delta(pool,config.height_to_no(end),-w);
+        }
+        self.set_staking(index,&staking);
+        Ok(())
+    }
+
+    //自动续期的质押没有到期日
+    fn expire_day(&self, staking: &Staking) -> Option<u64> {
+        if staking.auto_renew {
+            return None;
+        }
+        Some(self.height_to_no(staking.get_expire_height()))
+    }
+
+    //`height` 时锁定期的结束高度，自动续期的质押为当前周期结束
+    fn lock_end_height(&self, staking: &Staking, height: u64) -> u64 {
+        if staking.auto_renew {
+            return staking.get_period_end_height(height);
+        }
+        staking.get_expire_height()
+    }
+
+    /// 从 `today` 起质押权重改为 `new_w`，到期日改为 `new_expire`，之前的天按原权重结算
+    fn reweight_staking(&self, index: u128, new_w: Decimal, new_expire: Option<u64>, today: u64) {
+        let old = self.get_staking(index);
+        let pool = self.weight_pool(old.brc20_index);
+        self.accumulate(pool,today);
+
+        let old_expire = self.expire_day(&old);
+        let mut segments = self.get_weight_segments(index,&old,&self.config());
+        let old_w = segments.last().map_or(Decimal::ZERO,|segment| segment.weight);
+        match segments.last_mut() {
+            Some(segment) if segment.from_day >= today => segment.weight = new_w,
+            _ => segments.push(WeightSegment { from_day: today, weight: new_w }),
+        }
+        self.set_weight_segments(index,&segments);
+
+        self.add_weight_delta(pool,today,new_w - old_w);
+        if let Some(old_expire) = old_expire {
+            self.add_weight_delta(pool,old_expire,old_w);
+        }
+        if let Some(new_expire) = new_expire {
+            self.add_weight_delta(pool,new_expire,-new_w);
+        }
+    }
+
+    /// 锁定期内解质押：未释放收益按比例没收，其余立即释放，返回扣除的本金数量
+    ///
+    /// 白名单代币只代为托管，不扣除本金
+    fn settle_early_unstake(&self, index: u128) -> Result<u128> {
+        let config = self.config();
+        let Some(penalty) = config.early_unstake_penalty else {
+            return Ok(0);
+        };
+        let staking = self.get_staking(index);
+        //自动续期的质押在周期结束时解质押不算提前
+        if staking.unstaking_height >= self.lock_end_height(&staking,staking.unstaking_height.saturating_sub(1)) {
+            return Ok(0);
+        }
+
+        let (p,r,_) = self.calc_profit(index,staking.unstaking_height as u128)?;
+        let unreleased = p - r;
+        let forfeited = mul_rate(unreleased,penalty.reward_rate)?;
+        self.early_release_pointer(index).set_value::<u128>(unreleased - forfeited);
+        let is_coin = staking.is_alkane_staking() && staking.get_token_id() == self.get_coin_id();
+        let principal: u128 = if staking.is_alkane_staking() && !is_coin {
+            0
+        } else {
+            mul_rate(staking.brc20_value,penalty.principal_rate)?
+        };
+        //复投的 coin 扣除部分与没收收益去向相同，brc20 扣除部分由运营方处理
+        let forfeited = if is_coin { forfeited + principal } else { forfeited };
+        match penalty.destination {
+            PenaltyDestination::Burn => {
+                let mut burned = self.penalty_burned_pointer();
+                burned.set_value::<u128>(burned.get_value::<u128>() + forfeited);
+                self.burn_coins(forfeited)?;
+            }
+            PenaltyDestination::Redistribute => {
+                let pool = self.weight_pool(staking.brc20_index);
+                self.add_day_bonus(pool,self.height_to_no(staking.unstaking_height),forfeited);
+            }
+            PenaltyDestination::Treasury => {
+                let mut treasury = self.treasury_balance_pointer();
+                treasury.set_value::<u128>(treasury.get_value::<u128>() + forfeited);
+            }
+        }
+
+        Ok(principal)
+    }
+
+    //交给 coin 合约销毁
+    fn burn_coins(&self, value: u128) -> Result<()> {
+        if value == 0 {
+            return Ok(());
+        }
+        let cellpack = Cellpack {
+            target: self.get_coin_id(),
+            inputs: vec![88],
+        };
+        let parcel = AlkaneTransferParcel(vec![self.pay_coins(value)?]);
+        self.call(&cellpack, &parcel, self.fuel())?;
+        Ok(())
+    }
+
+    //alkane 的精度，只向 coin 合约查询 GetDecimals，其他 alkane 按白名单中登记的精度
+    fn token_decimals(&self, id: &AlkaneId) -> u8 {
+        if *id != self.get_coin_id() {
+            return self
+                .token_whitelist()
+                .iter()
+                .find(|token| token.id == [id.block, id.tx])
+                .map_or(ALKANE_DECIMALS, |token| token.decimals);
+        }
+        let cellpack = Cellpack {
+            target: *id,
+            inputs: vec![1004],
+        };
+        //返回值是 16 字节的 u128，其他长度的返回值不可信
+        self.staticcall(&cellpack, &AlkaneTransferParcel::default(), self.fuel())
+            .ok()
+            .and_then(|response| <[u8; 16]>::try_from(response.data.as_slice()).ok())
+            .and_then(|bytes| u8::try_from(u128::from_le_bytes(bytes)).ok())
+            .unwrap_or(ALKANE_DECIMALS)
+    }
+
+    fn withdraw_treasury(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let Some(treasury) = self.config().treasury else {
+            return Err(anyhow!("treasury not set"));
+        };
+        let treasury = AlkaneId { block: treasury[0], tx: treasury[1] };
+        if !context.incoming_alkanes.0.iter().any(|transfer| transfer.id == treasury && transfer.value >= 1) {
+            return Err(anyhow!("did not authenticate with the treasury token"));
+        }
+
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        let mut balance = self.treasury_balance_pointer();
+        let amount = balance.get_value::<u128>();
+        if amount > 0 {
+            response.alkanes.0.push(self.pay_coins(amount)?);
+            balance.set_value::<u128>(0);
+        }
+        Ok(response)
+    }
+
+    fn set_guardian(&self, block: u128, tx: u128) -> Result<CallResponse> {
+        self.only_owner()?;
+        let context = self.context()?;
+
+        let guardian = if block == 0 && tx == 0 { vec![] } else { vec![AlkaneId { block, tx }] };
+        self.guardian_pointer().set(Arc::new(alkane_ids_to_vec(&guardian)));
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    fn set_paused(&self, opcode: u128, paused: u128) -> Result<CallResponse> {
+        let context = self.context()?;
+        if !PAUSABLE_OPCODES.contains(&opcode) {
+            return Err(anyhow!("opcode cannot be paused"));
+        }
+        let mut list = self.paused_list();
+        if paused != 0 {
+            self.only_guardian()?;
+            if !list.contains(&opcode) {
+                list.push(opcode);
+            }
+        } else {
+            self.only_owner()?;
+            list.retain(|o| *o != opcode);
+        }
+        self.paused_pointer().set(Arc::new(Staking::serialize_invite_vec(&list)?));
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    fn set_emergency(&self, enabled: u128) -> Result<CallResponse> {
+        let context = self.context()?;
+        if enabled != 0 {
+            self.only_guardian()?;
+        } else {
+            self.only_owner()?;
+        }
+        self.emergency_pointer().set_value::<u8>((enabled != 0) as u8);
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    /// 紧急模式下退出质押，只退还本金，不扣除提前解质押的罚金
+    ///
+    /// 已产生的收益保留，解除紧急模式后仍可领取
+    fn emergency_withdraw(&self, index: u128) -> Result<CallResponse> {
+        let context = self.context()?;
+        if !self.is_emergency() {
+            return Err(anyhow!("pool is not in emergency mode"));
+        }
+        let caller_index = self.staking_id2index_pointer(&context.caller).get_value::<u128>();
+        if caller_index == 0 || caller_index != index {
+            return Err(anyhow!("caller is not staking"));
+        }
+
+        self.staking_unstaking(index)?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        let staking = self.get_staking(index);
+        if staking.is_alkane_staking() {
+            response.alkanes.0.push(self.return_tokens(&staking.get_token_id(), staking.brc20_value)?);
+        } else {
+            self.add_redemption(index, 0)?;
+        }
+        Ok(response)
+    }
+
+    /// 每个质押的收益向下取整，挖矿结束的质押领取时记录舍去的部分，挖矿结束后累计的整数部分转入国库
+    fn sweep_dust(&self) -> Result<CallResponse> {
+        self.only_owner()?;
+        let context = self.context()?;
+        let height = self.height();
+        if height <= self.config().mining_last_height {
+            return Err(anyhow!("Mining not ended"));
+        }
+        if self.config().treasury.is_none() {
+            return Err(anyhow!("treasury not set"));
+        }
+
+        let mut swept = self.dust_swept_pointer();
+        let dust = to_coin(self.get_reward_dust())?.saturating_sub(swept.get_value::<u128>());
+        if dust > 0 {
+            swept.set_value::<u128>(swept.get_value::<u128>() + dust);
+            let mut treasury = self.treasury_balance_pointer();
+            treasury.set_value::<u128>(treasury.get_value::<u128>() + dust);
+        }
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    /// `height` 之前所有权重池排放给质押的总量
+    fn reward_emitted(&self, height: u64) -> Result<u128> {
+        let config = self.config();
+        let today = self.height_to_no(height);
+        self.weight_pools()
+            .iter()
+            .try_fold(0u128,|total,pool| total.checked_add(self.get_emitted_at(*pool,today,&config)))
+            .ok_or_else(|| anyhow!("emission overflow"))
+    }
+
+    /// 领取时更新质押已分配的收益，挖矿结束后收益不再变化，记录取整舍去的部分
+    fn settle_reward(&self, index: u128, height: u64) -> Result<()> {
+        let staking = self.get_staking(index);
+        let exact = self.calc_profit_exact(index,&staking,height).map_or(Decimal::ZERO,|(p,_)| p);
+        let mined = to_coin(exact)?;
+        let dust = if height > self.config().mining_last_height || staking.get_mining_end_height(height) < height {
+            exact - Decimal::from(mined)
+        } else {
+            Decimal::ZERO
+        };
+
+        let mut allocated = self.staking_allocated_pointer(index);
+        let mut total = self.reward_allocated_pointer();
+        total.set_value::<u128>(total.get_value::<u128>() - allocated.get_value::<u128>() + mined);
+        allocated.set_value::<u128>(mined);
+
+        let total_dust = self.get_reward_dust() - self.get_staking_dust(index) + dust;
+        self.reward_dust_pointer().set(Arc::new(Staking::serialize_decimal(&total_dust)?));
+        self.staking_dust_pointer(index).set(Arc::new(Staking::serialize_decimal(&dust)?));
+        Ok(())
+    }
+
+    //拆分出的质押按比例分走已分配的收益和舍去的部分，总量不变
+    fn split_settlement(&self, index: u128, split_index: u128, f: Decimal) -> Result<()> {
+        let mut allocated = self.staking_allocated_pointer(index);
+        let value = allocated.get_value::<u128>();
+        let split_value: u128 = (Decimal::from(value) * f).floor().try_into()?;
+        allocated.set_value::<u128>(value - split_value);
+        self.staking_allocated_pointer(split_index).set_value::<u128>(split_value);
+
+        let dust = self.get_staking_dust(index);
+        let split_dust = dust * f;
+        self.staking_dust_pointer(index).set(Arc::new(Staking::serialize_decimal(&(dust - split_dust))?));
+        self.staking_dust_pointer(split_index).set(Arc::new(Staking::serialize_decimal(&split_dust)?));
+        Ok(())
+    }
+
+    //质押登记过的权重池，共享池总是在内
+    fn weight_pools(&self) -> Vec<u8> {
+        let mut pools = vec![0];
+        pools.extend(self.weight_pools_pointer().get().iter());
+        pools
+    }
+
+    fn register_weight_pool(&self, pool: u8) {
+        let mut pools = self.weight_pools_pointer();
+        let mut list = pools.get().as_ref().clone();
+        if pool != 0 && !list.contains(&pool) {
+            list.push(pool);
+            pools.set(Arc::new(list));
+        }
+    }
+
+    fn migrate_storage(&self, count: u128) -> Result<CallResponse> {
+        self.only_owner()?;
+        let context = self.context()?;
+
+        let total = self.get_orbital_count();
+        let mut cursor = self.migration_cursor_pointer();
+        let from = cursor.get_value::<u128>();
+        let to = min(from.saturating_add(count),total);
+        let config = self.config();
+        for index in from+1..=to {
+            self.migrate_staking(index,&config);
+        }
+        cursor.set_value::<u128>(to);
+        if to == total {
+            //旧版本的质押都加入权重变化后，累加器从头重新结算
+            if self.schema_version_pointer().get_value::<u32>() < SCHEMA_VERSION {
+                self.rebuild_accumulators();
+            }
+            self.schema_version_pointer().set_value::<u32>(SCHEMA_VERSION);
+        }
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
+    //按当前格式重写，读取时兼容的旧格式和默认值都写成显式的记录
+    fn migrate_staking(&self, index: u128, config: &PoolConfig) {
+        if self.staking_pointer(index).get().len() == 0 || self.staking_migrated_pointer(index).get_value::<u8>() == 1 {
+            return;
+        }
+        let staking = self.get_staking(index);
+        //没有开始计算收益的天的质押登记在累加器之前
+        if self.staking_start_day_pointer(index).get().len() == 0 {
+            self.seed_weight_deltas(index,&staking,config);
+        }
+        self.set_staking(index,&staking);
+        let staking_w = self.get_staking_w(index,&staking);
+        self.staking_w_pointer(index).set(Arc::new(Staking::serialize_decimal(&staking_w).unwrap()));
+        let ratio = self.get_staking_ratio(index);
+        self.staking_ratio_pointer(index).set(Arc::new(Staking::serialize_decimal(&ratio).unwrap()));
+        let start = self.get_staking_start_day(index,&staking,config);
+        self.staking_start_day_pointer(index).set_value::<u64>(start);
+        self.register_weight_pool(self.weight_pool(staking.brc20_index));
+        //重复迁移不能再次计入权重
+        self.staking_migrated_pointer(index).set_value::<u8>(1);
+    }
+
+    //旧版本质押的权重变化，从开始计算收益的天到解质押或到期
+    fn seed_weight_deltas(&self, index: u128, staking: &Staking, config: &PoolConfig) {
+        let pool = self.weight_pool(staking.brc20_index);
+        let start = self.get_staking_start_day(index,staking,config);
+        let end = config.height_to_no(staking.get_mining_end_height(u64::MAX));
+        if start < end {
+            let w = Decimal::from(staking.staking_value) * self.get_staking_w(index,staking);
+            self.add_weight_delta(pool,start,w);
+            self.add_weight_delta(pool,end,-w);
+        }
+    }
+
+    //清空所有权重池的结算进度，之后从第 0 天重新结算
+    fn rebuild_accumulators(&self) {
+        for pool in self.weight_pools() {
+            self.acc_pointer(pool).set(Arc::new(vec![]));
+            self.emitted_pointer(pool).set_value::<u128>(0);
+        }
+    }
+
+    //紧急模式下所有用户操作都暂停，迁移完成前累加器不包含旧版本的质押
+    fn check_paused(&self, opcode: u128) -> Result<()> {
+        if self.is_emergency() {
+            return Err(anyhow!("pool is in emergency mode"));
+        }
+        if self.schema_version_pointer().get_value::<u32>() < SCHEMA_VERSION {
+            return Err(anyhow!("storage migration in progress"));
+        }
+        if self.paused_list().contains(&opcode) {
+            return Err(anyhow!("opcode is paused"));
+        }
+        Ok(())
+    }
+
+    /// 记录需要退还的 brc20，退到交易见证中的脚本，没有则退到交易的第一个输出
+    fn add_redemption(&self, index: u128, penalty_value: u128) -> Result<()> {
+        let staking = self.get_staking(index);
+        let mut payout_script = self.witness_payload()?;
+        if payout_script.len() == 0 {
+            let tx = consensus_decode::<Transaction>(&mut Cursor::new(self.transaction()))?;
+            payout_script = tx.output.first().map(|out| out.script_pubkey.to_bytes()).unwrap_or_default();
+        }
+        let redemption = Redemption {
+            index,
+            brc20_index: staking.brc20_index,
+            tick: self.get_brc20_name(staking.brc20_index),
+            brc20_value: staking.brc20_value - penalty_value,
+            penalty_value,
+            payout_script,
+            unstaking_height: staking.unstaking_height,
+            fulfilled_txid: None,
+            fulfilled_height: 0,
+        };
+        self.redemption_pointer(index).set(Arc::new(redemption.serialize()?));
+
+        let mut pending = self.get_pending_redemption_list();
+        pending.push(index);
+        self.set_pending_redemption_list(&pending);
+        Ok(())
+    }
+
+    fn fulfill_redemption(&self, index: u128) -> Result<CallResponse> {
+        self.only_operator()?;
+        let context = self.context()?;
+
+        let Ok(txid) = <[u8; 32]>::try_from(self.witness_payload()?) else {
+            return Err(anyhow!("witness is not a txid"));
+        };
+        let Some(mut redemption) = self.get_redemption_record(index) else {
+            return Err(anyhow!("no redemption for this index"));
+        };
+        if redemption.is_fulfilled() {
+            return Err(anyhow!("redemption already fulfilled"));
+        }
+        redemption.fulfilled_txid = Some(txid);
+        redemption.fulfilled_height = self.height();
+        self.redemption_pointer(index).set(Arc::new(redemption.serialize()?));
+
+        let mut pending = self.get_pending_redemption_list();
+        pending.retain(|i| *i != index);
+        self.set_pending_redemption_list(&pending);
+
+        Ok(CallResponse::forward(&context.incoming_alkanes))
+    }
+
     fn claim(&self) -> Result<CallResponse> { 
+        self.check_paused(54)?;
         let context = self.context()?;
 
         let caller_index = self.staking_id2index_pointer(&context.caller).get_value::<u128>();
@@ -417,15 +1924,18 @@ impl StakingPool {
 
         let mut response = CallResponse::forward(&context.incoming_alkanes);
 
+        let staking = self.get_staking(caller_index);
+        self.accumulate(self.weight_pool(staking.brc20_index),self.height_to_no(self.height()));
+        self.settle_reward(caller_index,self.height())?;
         let (_,r,w) = self.calc_profit(caller_index,self.height() as u128)?;
         if r>w {
-            response.alkanes.0.push(AlkaneTransfer {
-                id: self.get_coin_id(),
-                value: r-w,
-            });
+            response.alkanes.0.push(self.pay_coins(r-w)?);
             let mut staking = self.get_staking(caller_index);
             staking.withdraw_coin_value += r-w;
             self.set_staking(caller_index, &staking);
+            let mut claimed = self.reward_claimed_pointer();
+            claimed.set_value::<u128>(claimed.get_value::<u128>() + r-w);
+            self.credit_referral(caller_index,r-w,&self.config())?;
         }
 
         
@@ -459,12 +1969,86 @@ impl StakingPool {
         Ok(())
     }
 
+    /// Verify that an operator token was sent along, the auth token when no operators
+    /// are configured
+    fn only_operator(&self) -> Result<()> {
+        let operators = self.operator_list();
+        if operators.len() == 0 {
+            return self.only_owner();
+        }
+        let context = self.context()?;
+        if !context
+            .incoming_alkanes
+            .0
+            .iter()
+            .any(|transfer| transfer.value >= 1 && operators.contains(&transfer.id))
+        {
+            return Err(anyhow!("did not authenticate with an operator token"));
+        }
+        Ok(())
+    }
+
+    /// Verify that the auth token alone or the guardian token was sent along
+    fn only_guardian(&self) -> Result<()> {
+        if self.only_owner().is_ok() {
+            return Ok(());
+        }
+        let context = self.context()?;
+        let guardian = self.guardian_list();
+        if !context
+            .incoming_alkanes
+            .0
+            .iter()
+            .any(|transfer| transfer.value >= 1 && guardian.contains(&transfer.id))
+        {
+            return Err(anyhow!("did not authenticate with the auth or guardian token"));
+        }
+        Ok(())
+    }
+
     ////////////////storage pointers///////////////////////////////////////
     /// 
+    fn config_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/config")
+    }
+
+    //未初始化配置的池使用默认配置
+    fn config(&self) -> PoolConfig {
+        let data = self.config_pointer().get();
+        if data.len() == 0 {
+            return PoolConfig::default();
+        }
+        //写入时已经校验过
+        PoolConfig::decode(&data).unwrap()
+    }
+
     fn coin_id_pointer(&self) -> StoragePointer {
         StoragePointer::from_keyword("/coin_id")
     }
 
+    //增发 coin 用的 auth token
+    fn coin_auth_id_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/coin_auth_id")
+    }
+
+    fn set_coin_auth_id(&self, id: &AlkaneId) {
+        let mut bytes = Vec::with_capacity(32);
+        bytes.extend_from_slice(&id.block.to_le_bytes());
+        bytes.extend_from_slice(&id.tx.to_le_bytes());
+        self.coin_auth_id_pointer().set(Arc::new(bytes));
+    }
+
+    fn get_coin_auth_id(&self) -> Option<AlkaneId> {
+        let bytes = self.coin_auth_id_pointer().get();
+        if bytes.len() < 32 {
+            return None;
+        }
+        Some(AlkaneId {
+            block: u128::from_le_bytes(bytes[0..16].try_into().unwrap()),
+            tx: u128::from_le_bytes(bytes[16..32].try_into().unwrap()),
+        })
+    }
+
     pub fn set_coin_id(&self, id: &AlkaneId) {
         let mut bytes = Vec::with_capacity(32);
         bytes.extend_from_slice(&id.block.to_le_bytes());
@@ -480,123 +2064,410 @@ impl StakingPool {
         }
     }
 
-    /// brc20 代币名字和index 存储
-    fn brc20_count_pointer(&self) -> StoragePointer {
-        StoragePointer::from_keyword("/brc20_count")
+    /// brc20 代币名字和index 存储
+    fn brc20_count_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/brc20_count")
+    }
+
+    fn get_brc20_count(&self) -> u8 {
+        self.brc20_count_pointer().get_value::<u8>()
+    }
+
+    fn set_brc20_count(&self, count: u8) {
+        self.brc20_count_pointer().set_value(count)
+    }
+
+    fn brc20_name_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/brc20_names")
+    }
+
+    fn add_brc20_name(&self, name: &str) {
+        let index = self.get_brc20_count();
+        self.brc20_name_pointer().select_index(index as u32).set(Arc::new(name.to_string().into_bytes()));
+        self.set_brc20_count(index.checked_add(1).expect("brc20 count overflow"));
+    }
+
+    fn get_brc20_name(&self,index:u8) -> String {
+        let name = self.brc20_name_pointer().select_index(index as u32).get();
+        String::from_utf8_lossy(&name).to_string()
+    }
+
+    fn brc20_ticker_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/brc20_tickers")
+    }
+
+    //没有参数的 brc20（0 号）按 1:1 参与共享排放
+    fn get_brc20_ticker(&self,index:u8) -> Brc20Ticker {
+        let data = self.brc20_ticker_pointer().select_index(index as u32).get();
+        if data.len() == 0 {
+            return Brc20Ticker { name: self.get_brc20_name(index), ..Default::default() };
+        }
+        Brc20Ticker::from_vec8(&data).unwrap()
+    }
+
+    fn token_whitelist_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/token_whitelist")
+    }
+
+    fn token_whitelist(&self) -> Vec<StakeToken> {
+        let data = self.token_whitelist_pointer().get();
+        if data.len() == 0 {
+            return vec![];
+        }
+        serde_json::from_slice(&data).unwrap()
+    }
+
+    fn weight_pool(&self, brc20_index: u8) -> u8 {
+        if self.get_brc20_ticker(brc20_index).day_volume > 0 {
+            brc20_index
+        } else {
+            0
+        }
+    }
+
+    //共享池按排放曲线，独立排放的 brc20 每天固定，另加当天重新分配的罚没收益
+    fn pool_day_volume(&self, pool: u8, day: u64, config: &PoolConfig) -> u128 {
+        let volume = if pool == 0 {
+            config.day_volume(day)
+        } else if day >= config.mining_days() {
+            0
+        } else {
+            self.get_brc20_ticker(pool).day_volume
+        };
+        volume + self.get_day_bonus(pool, day)
+    }
+
+    ///  质押凭证存款
+    fn orbital_count_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/orbital_count")
+    }
+
+    fn get_orbital_count(&self) -> u128 {
+        self.orbital_count_pointer().get_value::<u128>()
+    }
+
+    fn set_orbital_count(&self, count: u128) {
+        self.orbital_count_pointer().set_value(count)
+    }
+
+    fn staking_pointer(&self, index: u128) -> StoragePointer {
+        StoragePointer::from_keyword("/staking/").select(&index.to_le_bytes().to_vec())
+    }
+
+    fn staking_id2index_pointer(&self,alkane_id: &AlkaneId) -> StoragePointer{
+        let mut bytes = Vec::with_capacity(32);
+        bytes.extend_from_slice(&alkane_id.block.to_le_bytes());
+        bytes.extend_from_slice(&alkane_id.tx.to_le_bytes());
+        //TODO字符串长度反而更短
+        StoragePointer::from_keyword("/staking/id2index/").select(&bytes)
+    }
+    /// `today` 之前的天已经结算，之前质押的 brc20 从 `today` 开始计算收益
+    fn add_staking(&self,index: u128,staking: &Staking,period_w: Decimal,today: u64) {
+        self.staking_pointer(index).set(Arc::new(Staking::serialize(staking).unwrap()));
+        self.staking_id2index_pointer(&staking.get_alanes_id()).set_value(index);
+        self.index_invite(index,staking.invite_index);
+        self.staking_w_pointer(index).set(Arc::new(Staking::serialize_decimal(&period_w).unwrap()));
+        let curr_w =  Decimal::from(staking.staking_value) * period_w;
+        let pool = self.weight_pool(staking.brc20_index);
+        self.register_weight_pool(pool);
+
+        let acc = self.accumulate(pool,today);
+        let start = max(self.height_to_no(staking.staking_height),acc.day);
+        let expire = self.height_to_no(staking.get_expire_height());
+        self.staking_start_day_pointer(index).set_value::<u64>(start);
+        if start < expire {
+            self.add_weight_delta(pool,start,curr_w);
+            self.add_weight_delta(pool,expire,-curr_w);
+        }
+
+        self.set_orbital_count(index);
+    }
+
+    fn staking_unstaking(&self, index: u128) -> Result<()>{ 
+        let mut staking = self.get_staking(index);
+        if staking.unstaking_height>0 {
+            return Err(anyhow!("already unstaking"));
+        }
+        staking.unstaking_height = self.height();
+        self.staking_pointer(index).set(Arc::new(Staking::serialize(&staking).unwrap()));
+
+        //解质押当天起不再计算收益
+        let today = self.height_to_no(staking.unstaking_height);
+        let expire = self.height_to_no(staking.get_expire_height());
+        if expire <= today {
+            return Ok(());
+        }
+        let pool = self.weight_pool(staking.brc20_index);
+        self.accumulate(pool,today);
+        let curr_w =  Decimal::from(staking.staking_value) * self.get_staking_w(index,&staking);
+        self.add_weight_delta(pool,today,-curr_w);
+        if !staking.auto_renew {
+            self.add_weight_delta(pool,expire,curr_w);
+        }
+
+        Ok(())
+    }
+
+    //质押登记时的周期权重
+    fn staking_w_pointer(&self, index: u128) -> StoragePointer {
+        StoragePointer::from_keyword("/staking/weight/").select(&index.to_le_bytes().to_vec())
+    }
+
+    //没有记录权重的质押按照默认权重表，表外周期为1
+    fn get_staking_w(&self, index: u128, staking: &Staking) -> Decimal {
+        let data = self.staking_w_pointer(index).get();
+        if data.len() == 0 {
+            return PoolConfig::default().period_weight(staking.period).unwrap_or(Decimal::ONE);
+        }
+        Staking::descrialize_decimal(&data).unwrap()
+    }
+
+    //质押登记时的资产比例
+    fn staking_ratio_pointer(&self, index: u128) -> StoragePointer {
+        StoragePointer::from_keyword("/staking/ratio/").select(&index.to_le_bytes().to_vec())
+    }
+
+    //资产比例之前登记的质押比例为1
+    fn get_staking_ratio(&self, index: u128) -> Decimal {
+        let data = self.staking_ratio_pointer(index).get();
+        if data.len() == 0 {
+            return Decimal::ONE;
+        }
+        Staking::descrialize_decimal(&data).unwrap()
+    }
+
+    //开始计算收益的天
+    fn staking_start_day_pointer(&self, index: u128) -> StoragePointer {
+        StoragePointer::from_keyword("/staking/start_day/").select(&index.to_le_bytes().to_vec())
+    }
+
+    //已经迁移到当前格式的质押
+    fn staking_migrated_pointer(&self, index: u128) -> StoragePointer {
+        StoragePointer::from_keyword("/staking/migrated/").select(&index.to_le_bytes().to_vec())
+    }
+
+    //追加或延长后的分段权重
+    fn weight_segments_pointer(&self, index: u128) -> StoragePointer {
+        StoragePointer::from_keyword("/staking/segments/").select(&index.to_le_bytes().to_vec())
+    }
+
+    //没有修改过的质押只有一段
+    fn get_weight_segments(&self, index: u128, staking: &Staking, config: &PoolConfig) -> Vec<WeightSegment> {
+        let data = self.weight_segments_pointer(index).get();
+        if data.len() == 0 {
+            return vec![WeightSegment {
+                from_day: self.get_staking_start_day(index, staking, config),
+                weight: Decimal::from(staking.staking_value) * self.get_staking_w(index, staking),
+            }];
+        }
+        WeightSegment::descrialize_vec(&data).unwrap()
+    }
+
+    fn set_weight_segments(&self, index: u128, segments: &Vec<WeightSegment>) {
+        self.weight_segments_pointer(index).set(Arc::new(WeightSegment::serialize_vec(segments).unwrap()));
+    }
+
+    fn get_staking_start_day(&self, index: u128, staking: &Staking, config: &PoolConfig) -> u64 {
+        let data = self.staking_start_day_pointer(index).get();
+        if data.len() == 0 {
+            return config.height_to_no(staking.staking_height);
+        }
+        self.staking_start_day_pointer(index).get_value::<u64>()
+    }
+
+    //提前解质押时立即释放的收益
+    fn early_release_pointer(&self, index: u128) -> StoragePointer {
+        StoragePointer::from_keyword("/staking/early_release/").select(&index.to_le_bytes().to_vec())
+    }
+
+    fn get_early_release(&self, index: u128, staking: &Staking, height: u64) -> u128 {
+        if staking.unstaking_height == 0 || height < staking.unstaking_height {
+            return 0;
+        }
+        self.early_release_pointer(index).get_value::<u128>()
+    }
+
+    fn penalty_burned_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/penalty/burned")
+    }
+
+    fn treasury_balance_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/treasury_balance")
+    }
+
+    //已领取的挖矿收益
+    fn reward_claimed_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/reward/claimed")
+    }
+
+    //已转入国库的无法领取的收益
+    fn dust_swept_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/reward/swept")
+    }
+
+    //所有质押最近一次领取时的挖矿收益之和
+    fn reward_allocated_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/reward/allocated")
+    }
+
+    //挖矿结束的质押取整舍去的收益之和
+    fn reward_dust_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/reward/dust")
+    }
+
+    fn get_reward_dust(&self) -> Decimal {
+        let data = self.reward_dust_pointer().get();
+        if data.len() == 0 {
+            return Decimal::ZERO;
+        }
+        Staking::descrialize_decimal(&data).unwrap()
+    }
+
+    fn staking_allocated_pointer(&self, index: u128) -> StoragePointer {
+        StoragePointer::from_keyword("/staking/allocated/").select(&index.to_le_bytes().to_vec())
+    }
+
+    fn staking_dust_pointer(&self, index: u128) -> StoragePointer {
+        StoragePointer::from_keyword("/staking/dust/").select(&index.to_le_bytes().to_vec())
+    }
+
+    fn get_staking_dust(&self, index: u128) -> Decimal {
+        let data = self.staking_dust_pointer(index).get();
+        if data.len() == 0 {
+            return Decimal::ZERO;
+        }
+        Staking::descrialize_decimal(&data).unwrap()
+    }
+
+    fn weight_pools_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/weight_pools")
+    }
+
+    fn get_staking(&self, index: u128) -> Staking {
+        let data = self.staking_pointer(index).get();
+        Staking::descrialize(&data).unwrap()
+    }
+
+    fn set_staking(&self, index: u128, staking: &Staking) {
+        self.staking_pointer(index).set(Arc::new(Staking::serialize(staking).unwrap()));
+    }
+
+    fn get_staking_by_id(&self, alkane_id: &AlkaneId) ->Staking {
+        let index = self.staking_id2index_pointer(alkane_id).get_value::<u128>();
+        self.get_staking(index)
+    }
+
+    //区块头跟踪
+    fn header_pointer(&self, height: u64) -> StoragePointer {
+        StoragePointer::from_keyword("/headers/").select(&height.to_le_bytes().to_vec())
+    }
+
+    fn get_header(&self, height: u64) -> Option<Header> {
+        let data = self.header_pointer(height).get();
+        if data.len() == 0 {
+            return None;
+        }
+        consensus_decode::<Header>(&mut Cursor::new(data.as_ref().clone())).ok()
+    }
+
+    fn set_header(&self, height: u64, header: &Header) {
+        self.header_pointer(height).set(Arc::new(bitcoin::consensus::serialize(header)));
     }
 
-    fn get_brc20_count(&self) -> u8 {
-        self.brc20_count_pointer().get_value::<u8>()
+    fn header_tip_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/header_tip")
     }
 
-    fn set_brc20_count(&self, count: u8) {
-        self.brc20_count_pointer().set_value(count)
+    fn header_tip(&self) -> u64 {
+        self.header_tip_pointer().get_value::<u64>()
     }
 
-    fn brc20_name_pointer(&self) -> StoragePointer {
-        StoragePointer::from_keyword("/brc20_names")
+    fn deposit_script_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/deposit_script")
     }
 
-    fn add_brc20_name(&self, name: &str) {
-        let index = self.get_brc20_count();
-        self.brc20_name_pointer().select_index(index as u32).set(Arc::new(name.to_string().into_bytes()));
-        self.set_brc20_count(index.checked_add(1).expect("brc20 count overflow"));
+    //运营方确认过余额的 transfer 铭文，按 reveal 交易的 wtxid 记录
+    fn deposit_attested_pointer(&self, wtxid: &[u8]) -> StoragePointer {
+        StoragePointer::from_keyword("/staking/attested/").select(&wtxid.to_vec())
     }
 
-    fn get_brc20_name(&self,index:u8) -> String {
-        let name = self.brc20_name_pointer().select_index(index as u32).get();
-        String::from_utf8_lossy(&name).to_string()
+    //已经用于质押的存款交易
+    fn deposit_used_pointer(&self, txid: &[u8; 32]) -> StoragePointer {
+        StoragePointer::from_keyword("/staking/deposit/").select(&txid.to_vec())
     }
 
-    ///  质押凭证存款
-    fn orbital_count_pointer(&self) -> StoragePointer {
-        StoragePointer::from_keyword("/orbital_count")
+    //运营方授权
+    fn operators_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/operators")
     }
 
-    fn get_orbital_count(&self) -> u128 {
-        self.orbital_count_pointer().get_value::<u128>()
+    fn operator_list(&self) -> Vec<AlkaneId> {
+        alkane_ids_from_vec(&self.operators_pointer().get())
     }
 
-    fn set_orbital_count(&self, count: u128) {
-        self.orbital_count_pointer().set_value(count)
+    fn set_operators(&self, operators: &Vec<AlkaneId>) {
+        self.operators_pointer().set(Arc::new(alkane_ids_to_vec(operators)));
     }
 
-    fn staking_pointer(&self, index: u128) -> StoragePointer {
-        StoragePointer::from_keyword("/staking/").select(&index.to_le_bytes().to_vec())
+    //存储格式版本
+    fn schema_version_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/schema_version")
     }
 
-    fn staking_id2index_pointer(&self,alkane_id: &AlkaneId) -> StoragePointer{
-        let mut bytes = Vec::with_capacity(32);
-        bytes.extend_from_slice(&alkane_id.block.to_le_bytes());
-        bytes.extend_from_slice(&alkane_id.tx.to_le_bytes());
-        //TODO字符串长度反而更短
-        StoragePointer::from_keyword("/staking/id2index/").select(&bytes)
+    //已经迁移到的质押
+    fn migration_cursor_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/migration_cursor")
     }
-    fn add_staking(&self,index: u128,staking: &Staking) {
-        self.staking_pointer(index).set(Arc::new(Staking::serialize(staking).unwrap()));
-        self.staking_id2index_pointer(&staking.get_alanes_id()).set_value(index);
-        self.index_invite(index,staking.invite_index);
-        let curr_w =  Decimal::from(staking.staking_value) * period_to_w(staking.period);
 
-        let h_w = self.get_staking_weight(self.height_to_no(staking.staking_height));
-        self.set_staking_weight(self.height_to_no(staking.staking_height), h_w + curr_w);
-        let h_exp_w = self.get_staking_expire(self.height_to_no(staking.get_expire_height()));
-        self.set_staking_expire(self.height_to_no(staking.get_expire_height()), h_exp_w + curr_w);
+    //暂停
+    fn guardian_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/guardian")
+    }
 
+    fn guardian_list(&self) -> Vec<AlkaneId> {
+        alkane_ids_from_vec(&self.guardian_pointer().get())
+    }
 
-        // let mut stat = self.get_staking_stat(staking.staking_height);
-        // stat.staking_weight += curr_w;
-        // stat.init_weight += curr_w;
-        // self.set_staking_stat(staking.staking_height,&stat);
-        // let mut stat2 = self.get_staking_stat(staking.expire_height);
-        // stat2.expire_weight += curr_w;
-        // self.set_staking_stat(staking.expire_height, &stat2);
-        self.set_orbital_count(index);
+    fn paused_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/paused")
     }
 
-    fn staking_unstaking(&self, index: u128) -> Result<()>{ 
-        let mut staking = self.get_staking(index);
-        if staking.unstaking_height>0 {
-            return Err(anyhow!("already unstaking"));
-        }
-        staking.unstaking_height = self.height();
-        self.staking_pointer(index).set(Arc::new(Staking::serialize(&staking).unwrap()));
-        if staking.get_expire_height() <= self.height() {
-            return Ok(());
+    fn paused_list(&self) -> Vec<u128> {
+        let data = self.paused_pointer().get();
+        if data.len() == 0 {
+            return vec![];
         }
+        Staking::descrialize_invite_vec(&data).unwrap()
+    }
 
-        let curr_w =  Decimal::from(staking.staking_value) * period_to_w(staking.period);
-        let h_w = self.get_staking_weight(self.height_to_no(staking.unstaking_height));
-        self.set_staking_weight(staking.unstaking_height, h_w - curr_w);
-        let h_exp_w = self.get_staking_expire(self.height_to_no(staking.get_expire_height()));
-        self.set_staking_expire(self.height_to_no(staking.get_expire_height()), h_exp_w - curr_w);
+    fn emergency_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/emergency")
+    }
 
-        Ok(())
-        // let mut stat = self.get_staking_stat(staking.unstaking_height);
-        // let curr_w =  Decimal::from(staking.staking_value) * period_to_w(staking.period);
-        // stat.unstaking_weight += curr_w;
-        // self.set_staking_stat(staking.unstaking_height,&stat);
+    fn is_emergency(&self) -> bool {
+        self.emergency_pointer().get_value::<u8>() == 1
+    }
 
-        
-        // let mut stat = self.get_staking_stat(staking.expire_height);
-        // let curr_w =  Decimal::from(staking.staking_value) * period_to_w(staking.period);
-        // stat.expire_weight -= curr_w;
-        // self.set_staking_stat(staking.expire_height,&stat);
+    fn operator_threshold_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/operator_threshold")
+    }
 
+    fn get_operator_threshold(&self) -> u128 {
+        self.operator_threshold_pointer().get_value::<u128>()
     }
 
-    fn get_staking(&self, index: u128) -> Staking {
-        let data = self.staking_pointer(index).get();
-        Staking::descrialize(&data).unwrap()
+    //待达到阈值的质押登记
+    fn staking_approvals_pointer(&self, key: &Vec<u8>) -> StoragePointer {
+        StoragePointer::from_keyword("/staking/approvals/").select(key)
     }
 
-    fn set_staking(&self, index: u128, staking: &Staking) {
-        self.staking_pointer(index).set(Arc::new(Staking::serialize(staking).unwrap()));
+    fn get_staking_approvals(&self, key: &Vec<u8>) -> Vec<AlkaneId> {
+        alkane_ids_from_vec(&self.staking_approvals_pointer(key).get())
     }
 
-    fn get_staking_by_id(&self, alkane_id: &AlkaneId) ->Staking {
-        let index = self.staking_id2index_pointer(alkane_id).get_value::<u128>();
-        self.get_staking(index)
+    //已登记的质押，防止重放
+    fn staking_registered_pointer(&self, key: &Vec<u8>) -> StoragePointer {
+        StoragePointer::from_keyword("/staking/registered/").select(key)
     }
 
     //邀请关系存款
@@ -612,9 +2483,30 @@ impl StakingPool {
 
     fn get_invite_indexs(&self, index: u128) -> Vec<u128> {
         let data = self.staking_invite_pointer(index).get();
+        if data.len() == 0 {
+            return vec![];
+        }
         Staking::descrialize_invite_vec(&data).unwrap()
     }
 
+    //已领取的推荐奖励
+    fn referral_claimed_pointer(&self, index: u128) -> StoragePointer {
+        StoragePointer::from_keyword("/staking/referral_claimed/").select(&index.to_le_bytes().to_vec())
+    }
+
+    //被邀请人领取收益时记给邀请人的推荐奖励
+    fn referral_credited_pointer(&self, index: u128) -> StoragePointer {
+        StoragePointer::from_keyword("/staking/referral_credited/").select(&index.to_le_bytes().to_vec())
+    }
+
+    fn referral_credited_total_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/referral/credited")
+    }
+
+    fn referral_claimed_total_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/referral/claimed")
+    }
+
     //建立邀请关系索引
     fn index_invite(&self, index: u128, invite_index: u128){
         if invite_index>0 {
@@ -625,6 +2517,35 @@ impl StakingPool {
         }
     }
 
+    //解质押的 brc20 退还记录
+    fn redemption_pointer(&self, index: u128) -> StoragePointer {
+        StoragePointer::from_keyword("/redemption/").select(&index.to_le_bytes().to_vec())
+    }
+
+    fn get_redemption_record(&self, index: u128) -> Option<Redemption> {
+        let data = self.redemption_pointer(index).get();
+        if data.len() == 0 {
+            return None;
+        }
+        Some(Redemption::descrialize(&data).unwrap())
+    }
+
+    fn pending_redemptions_pointer(&self) -> StoragePointer {
+        StoragePointer::from_keyword("/redemption_pending")
+    }
+
+    fn get_pending_redemption_list(&self) -> Vec<u128> {
+        let data = self.pending_redemptions_pointer().get();
+        if data.len() == 0 {
+            return vec![];
+        }
+        Staking::descrialize_invite_vec(&data).unwrap()
+    }
+
+    fn set_pending_redemption_list(&self, indexs: &Vec<u128>) {
+        self.pending_redemptions_pointer().set(Arc::new(Staking::serialize_invite_vec(indexs).unwrap()));
+    }
+
     // fn staking_stat_pointer(&self, height: u64) -> StoragePointer {
     //     StoragePointer::from_keyword("/staking_stat").select(&height.to_le_bytes().to_vec())
     // }
@@ -659,16 +2580,59 @@ impl StakingPool {
     //     self.staking_stat_pointer(height).set(Arc::new(StakingStat::serialize(stat).unwrap()));
     // }
 
-    fn staking_weight_pointer(&self, height: u64) -> StoragePointer {
-        StoragePointer::from_keyword("/staking_weight/").select(&height.to_le_bytes().to_vec())
+    //独立排放的 brc20 使用自己的权重池，0 号池沿用原来的存储位置
+    fn weight_pool_pointer(&self, pool: u8, keyword: &str) -> StoragePointer {
+        if pool == 0 {
+            StoragePointer::from_keyword(keyword)
+        } else {
+            StoragePointer::from_keyword("/brc20_pool/").select(&vec![pool]).keyword(keyword)
+        }
+    }
+
+    fn acc_pointer(&self, pool: u8) -> StoragePointer {
+        self.weight_pool_pointer(pool, "/staking_acc")
+    }
+
+    //每天结算后的累加器快照
+    fn acc_day_pointer(&self, pool: u8, day: u64) -> StoragePointer {
+        self.weight_pool_pointer(pool, "/staking_acc/").select(&day.to_le_bytes().to_vec())
+    }
+
+    //当天生效的权重变化
+    fn weight_delta_pointer(&self, pool: u8, day: u64) -> StoragePointer {
+        self.weight_pool_pointer(pool, "/staking_delta/").select(&day.to_le_bytes().to_vec())
+    }
+
+    //当天额外分配的收益
+    fn day_bonus_pointer(&self, pool: u8, day: u64) -> StoragePointer {
+        self.weight_pool_pointer(pool, "/staking_bonus/").select(&day.to_le_bytes().to_vec())
     }
 
-    fn staking_expire_pointer(&self, height: u64) -> StoragePointer {
-        StoragePointer::from_keyword("/staking_expire/").select(&height.to_le_bytes().to_vec())
+    fn get_day_bonus(&self, pool: u8, day: u64) -> u128 {
+        self.day_bonus_pointer(pool, day).get_value::<u128>()
     }
 
-    fn get_staking_expire(&self, height: u64) -> Decimal {
-        let v = self.staking_expire_pointer(height).get();
+    //只能加到还没有结算的天
+    fn add_day_bonus(&self, pool: u8, day: u64, value: u128) {
+        let mut bonus = self.day_bonus_pointer(pool, day);
+        bonus.set_value::<u128>(bonus.get_value::<u128>() + value);
+    }
+
+    //已结算的天排放给质押的总量
+    fn emitted_pointer(&self, pool: u8) -> StoragePointer {
+        self.weight_pool_pointer(pool, "/staking_emitted")
+    }
+
+    fn get_acc(&self, pool: u8) -> StakingAcc {
+        let data = self.acc_pointer(pool).get();
+        if data.len() == 0 {
+            return StakingAcc::default();
+        }
+        StakingAcc::descrialize(&data).unwrap()
+    }
+
+    fn get_weight_delta(&self, pool: u8, day: u64) -> Decimal {
+        let v = self.weight_delta_pointer(pool, day).get();
         if v.len()>0 {
             Staking::descrialize_decimal(&v).unwrap()
         }else{
@@ -676,40 +2640,69 @@ impl StakingPool {
         }
     }
 
-    fn set_staking_expire(&self, height: u64, value: Decimal) {
-        self.staking_expire_pointer(height).set(Arc::new(Staking::serialize_decimal(&value).unwrap()));
+    fn add_weight_delta(&self, pool: u8, day: u64, w: Decimal) {
+        let value = self.get_weight_delta(pool, day) + w;
+        self.weight_delta_pointer(pool, day).set(Arc::new(Staking::serialize_decimal(&value).unwrap()));
     }
 
-    fn get_staking_weight(&self, height: u64) -> Decimal { 
-        let v = self.staking_weight_pointer(height).get();
-        if v.len()>0 {
-            return Staking::descrialize_decimal(&v).unwrap();
+    //返回当天排放给质押的数量
+    fn acc_next(&self, pool: u8, acc: &mut StakingAcc, config: &PoolConfig) -> u128 {
+        let day = acc.day;
+        let volume = self.pool_day_volume(pool, day, config);
+        acc.next(self.get_weight_delta(pool, day), volume);
+        if acc.weight > Decimal::ZERO { volume } else { 0 }
+    }
+
+    /// 结算 `day` 之前的所有天，每天只结算一次
+    fn accumulate(&self, pool: u8, day: u64) -> StakingAcc {
+        let config = self.config();
+        let mut acc = self.get_acc(pool);
+        if acc.day >= day {
+            return acc;
+        }
+        let mut emitted = self.emitted_pointer(pool);
+        let mut total = emitted.get_value::<u128>();
+        while acc.day < day {
+            total += self.acc_next(pool, &mut acc, &config);
+            self.acc_day_pointer(pool, acc.day).set(Arc::new(acc.serialize().unwrap()));
+        }
+        self.acc_pointer(pool).set(Arc::new(acc.serialize().unwrap()));
+        emitted.set_value::<u128>(total);
+        acc
+    }
+
+    /// 第 `day` 天之前排放给质押的总量，没有质押的天不排放
+    fn get_emitted_at(&self, pool: u8, day: u64, config: &PoolConfig) -> u128 {
+        let mut acc = self.get_acc(pool);
+        let mut total = self.emitted_pointer(pool).get_value::<u128>();
+        while acc.day < day {
+            total += self.acc_next(pool, &mut acc, config);
         }
+        total
+    }
 
-        let exp = self.get_staking_expire(height);
-        let mut w = Decimal::from(0)-exp;
-        let mut height = height;
-        while height>0 {
-            height -= 1;
-            let v = self.staking_weight_pointer(height).get();
-            if v.len()>0 {
-                w +=  Staking::descrialize_decimal(&v).unwrap();
-                break;
-            }else{
-                w -= self.get_staking_expire(height);
+    /// 第 `day` 天开始时的累加器，未结算的天按当前权重推算
+    fn get_acc_at(&self, pool: u8, day: u64, config: &PoolConfig) -> StakingAcc {
+        let mut acc = self.get_acc(pool);
+        if day < acc.day {
+            let data = self.acc_day_pointer(pool, day).get();
+            if data.len() == 0 {
+                return StakingAcc::default();
             }
+            return StakingAcc::descrialize(&data).unwrap();
         }
-        return w;
+        while acc.day < day {
+            self.acc_next(pool, &mut acc, config);
+        }
+        acc
     }
 
     fn height_to_no(&self, height: u64) -> u64{
-        (height - MINING_FIRST_HEIGHT)/144
-    }
-    fn set_staking_weight(& self, height: u64, w: Decimal) {
-        self.staking_weight_pointer(height).set(Arc::new(Staking::serialize_decimal(&w).unwrap()));
+        self.config().height_to_no(height)
     }
 
 
+
     /// Get the name of the collection
     fn get_name(&self) -> Result<CallResponse> {
         let context = self.context()?;
@@ -758,7 +2751,17 @@ impl StakingPool {
         let mut response = CallResponse::forward(&context.incoming_alkanes);
 
         let staking = self.get_staking(index);
-        response.data = serde_json::to_vec(&staking)?;
+        let mut attributes = serde_json::to_value(&staking)?;
+        //附加按精度格式化的金额，brc20 统一按 18 位小数存储
+        let value_decimals = if staking.token_id == [0, 0] {
+            BRC20_DECIMALS as u8
+        } else {
+            self.token_decimals(&AlkaneId { block: staking.token_id[0], tx: staking.token_id[1] })
+        };
+        attributes["brc20_value_formatted"] = format_amount(staking.brc20_value, value_decimals).into();
+        attributes["withdraw_coin_value_formatted"] =
+            format_amount(staking.withdraw_coin_value, self.token_decimals(&self.get_coin_id())).into();
+        response.data = serde_json::to_vec(&attributes)?;
         Ok(response)
     }
 
@@ -780,6 +2783,160 @@ impl StakingPool {
         Ok(response)
     }
 
+    pub fn get_header_tip(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        let tip = self.header_tip();
+        let hash = self
+            .get_header(tip)
+            .map(|header| header.block_hash().to_string())
+            .unwrap_or_default();
+        response.data = format!("{}:{}", tip, hash).into_bytes();
+        Ok(response)
+    }
+
+    pub fn get_config(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        response.data = self.config().to_vec8()?;
+        Ok(response)
+    }
+
+    pub fn get_period_weights(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        response.data = serde_json::to_vec(&self.config().period_weights)?;
+        Ok(response)
+    }
+
+    pub fn get_invitees(&self, index: u128) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        let invitees: Vec<String> = self.get_invite_indexs(index).iter().map(|i| i.to_string()).collect();
+        response.data = serde_json::to_vec(&invitees)?;
+        Ok(response)
+    }
+
+    /// 推荐奖励在被邀请人领取时记入，与 `height` 无关
+    pub fn get_referral_earnings(&self, index: u128, _height: u128) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        let earned = self.referral_credited_pointer(index).get_value::<u128>();
+        let claimed = self.referral_claimed_pointer(index).get_value::<u128>();
+        response.data = serde_json::to_vec(&[earned.to_string(),claimed.to_string()])?;
+        Ok(response)
+    }
+
+    pub fn get_brc20_tickers(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        let tickers: Vec<Brc20Ticker> = (0..self.get_brc20_count()).map(|i| self.get_brc20_ticker(i)).collect();
+        response.data = serde_json::to_vec(&tickers)?;
+        Ok(response)
+    }
+
+    pub fn get_redemption(&self, index: u128) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        let Some(redemption) = self.get_redemption_record(index) else {
+            return Err(anyhow!("no redemption for this index"));
+        };
+        response.data = serde_json::to_vec(&serde_json::json!({
+            "index": redemption.index.to_string(),
+            "tick": redemption.tick,
+            "brc20_value": redemption.brc20_value.to_string(),
+            "penalty_value": redemption.penalty_value.to_string(),
+            "payout_script": hex::encode(&redemption.payout_script),
+            "unstaking_height": redemption.unstaking_height,
+            "fulfilled_txid": redemption.fulfilled_txid.map(|txid| bitcoin::Txid::from_byte_array(txid).to_string()),
+            "fulfilled_height": redemption.fulfilled_height,
+        }))?;
+        Ok(response)
+    }
+
+    pub fn get_penalty_stats(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        response.data = serde_json::to_vec(&serde_json::json!({
+            "burned": self.penalty_burned_pointer().get_value::<u128>().to_string(),
+            "treasury": self.treasury_balance_pointer().get_value::<u128>().to_string(),
+        }))?;
+        Ok(response)
+    }
+
+    pub fn get_token_whitelist(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        response.data = serde_json::to_vec(&self.token_whitelist())?;
+        Ok(response)
+    }
+
+    pub fn get_reward_stats(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        let emitted = self.reward_emitted(self.height())?;
+        let swept = self.dust_swept_pointer().get_value::<u128>();
+        response.data = serde_json::to_vec(&serde_json::json!({
+            "emitted": emitted.to_string(),
+            "allocated": self.reward_allocated_pointer().get_value::<u128>().to_string(),
+            "claimed": self.reward_claimed_pointer().get_value::<u128>().to_string(),
+            "dust": to_coin(self.get_reward_dust())?.saturating_sub(swept).to_string(),
+            "swept": swept.to_string(),
+            "referral_credited": self.referral_credited_total_pointer().get_value::<u128>().to_string(),
+            "referral_claimed": self.referral_claimed_total_pointer().get_value::<u128>().to_string(),
+        }))?;
+        Ok(response)
+    }
+
+    pub fn get_schema_version(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        response.data = serde_json::to_vec(&serde_json::json!({
+            "schema_version": self.schema_version_pointer().get_value::<u32>(),
+            "latest": SCHEMA_VERSION,
+            "staking_version": STAKING_VERSION,
+            "migrated": self.migration_cursor_pointer().get_value::<u128>().to_string(),
+            "total": self.get_orbital_count().to_string(),
+        }))?;
+        Ok(response)
+    }
+
+    pub fn get_pause_state(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        let paused: Vec<String> = self.paused_list().iter().map(|o| o.to_string()).collect();
+        let guardian = self.guardian_list().first().map(|id| format!("{}:{}", id.block, id.tx));
+        response.data = serde_json::to_vec(&serde_json::json!({
+            "paused": paused,
+            "emergency": self.is_emergency(),
+            "guardian": guardian,
+        }))?;
+        Ok(response)
+    }
+
+    pub fn get_pending_redemptions(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        let pending: Vec<String> = self.get_pending_redemption_list().iter().map(|i| i.to_string()).collect();
+        response.data = serde_json::to_vec(&pending)?;
+        Ok(response)
+    }
+
+    pub fn get_operators(&self) -> Result<CallResponse> {
+        let context = self.context()?;
+        let mut response = CallResponse::forward(&context.incoming_alkanes);
+        let operators: Vec<String> = self
+            .operator_list()
+            .iter()
+            .map(|id| format!("{}:{}", id.block, id.tx))
+            .collect();
+        response.data = serde_json::to_vec(&serde_json::json!({
+            "operators": operators,
+            "threshold": self.get_operator_threshold().to_string(),
+        }))?;
+        Ok(response)
+    }
+
     pub fn set_storge(&self,key: Vec<u8>,value: Vec<u8>) -> (){
         StoragePointer::wrap(&key).set(Arc::new(value));
     }
@@ -820,6 +2977,24 @@ mod test{
         assert_eq!(s.get_coin_id(),alkanes_id);
     }
 
+    #[wasm_bindgen_test]
+    fn test_to_coin(){
+        // 不足一个单位的部分直接舍去, 不能进位
+        assert_eq!(to_coin(Decimal::from_str("9.999999999").unwrap()).unwrap(),9);
+        assert_eq!(to_coin(Decimal::from_str("10").unwrap()).unwrap(),10);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_mul_rate(){
+        //超出 Decimal 范围的 brc20 数量
+        let value = 100_000_000_000u128 * 10u128.pow(18);
+        assert_eq!(mul_rate(value,Decimal::from_str("0.1").unwrap()).unwrap(),value / 10);
+        assert_eq!(mul_rate(7,Decimal::from_str("0.5").unwrap()).unwrap(),3);
+        assert_eq!(mul_div(value,1,3).unwrap(),value / 3);
+        assert!(mul_rate(1,Decimal::from_str("-0.1").unwrap()).is_err());
+        assert!(mul_div(u128::MAX,2,1).is_err());
+    }
+
     // #[wasm_bindgen_test]
     // fn test_get_profit(){
     //     let sp = StakingPool::default();
@@ -868,9 +3043,12 @@ mod test{
             staking_height: 455,
             unstaking_height: 0,
             alkanes_id: [2,111128],
-            withdraw_coin_value: 0 };
+            withdraw_coin_value: 0,
+            token_id: [0,0],
+            auto_renew: false,
+            renewals: 0 };
 
-        sp.add_staking(index as u128, &staking);
+        sp.add_staking(index as u128, &staking, Decimal::ONE, sp.height_to_no(staking.staking_height));
 
         let (p,r,w) = sp.calc_profit(index as u128, 468).unwrap();
         let (p1,r1,w1) =sp.calc_profit_1(index as u128, 468).unwrap();
@@ -901,4 +3079,312 @@ mod test{
         assert_eq!(w,w1);
 
     }
-}
\ No newline at end of file
+
+    //单元测试共享存储，每个测试使用独立排放的 brc20 权重池互不影响
+    fn own_pool(sp: &StakingPool, name: &str, day_volume: u128) -> u8 {
+        if sp.get_brc20_count() == 0 {
+            sp.add_brc20_name(BRC20_NAME_0);
+        }
+        let index = sp.get_brc20_count();
+        let ticker = Brc20Ticker { name: String::from(name), day_volume, ..Default::default() };
+        sp.brc20_ticker_pointer().select_index(index as u32).set(Arc::new(ticker.to_vec8().unwrap()));
+        sp.add_brc20_name(name);
+        index
+    }
+
+    fn new_staking(brc20_index: u8, staking_value: u128, staking_height: u64, invite_index: u128, id: u128) -> Staking {
+        Staking { brc20_index,
+            brc20_value: 800000000,
+            staking_value, period: 30,
+            tx: [0;32],
+            invite_index,
+            staking_height,
+            unstaking_height: 0,
+            alkanes_id: [2,id],
+            withdraw_coin_value: 0,
+            token_id: [0,0],
+            auto_renew: false,
+            renewals: 0 }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_referral(){
+        let sp = StakingPool::default();
+        let mut config = PoolConfig::default();
+        config.referral_rates = vec![Decimal::from_str("0.1").unwrap(),Decimal::from_str("0.05").unwrap()];
+        config.mining_cap = 60000000000000000;
+        sp.config_pointer().set(Arc::new(config.to_vec8().unwrap()));
+        let brc20 = own_pool(&sp, "refr", 1000000);
+        let count = sp.get_orbital_count();
+        let staking_height = 455;
+
+        //1 邀请 2，2 邀请 3
+        let (a, b, c) = (count + 1, count + 2, count + 3);
+        for (index, invite_index) in [(a, 0), (b, a), (c, b)] {
+            let staking = new_staking(brc20, 50000 * (index - count), staking_height, invite_index, 200 + index);
+            sp.add_staking(index, &staking, Decimal::ONE, 0);
+        }
+        assert_eq!(sp.get_invite_indexs(a), vec![b]);
+        assert_eq!(sp.get_invite_indexs(b), vec![c]);
+
+        //领取时沿邀请链记入，每一级按各自比例
+        let total = sp.referral_credited_total_pointer().get_value::<u128>();
+        sp.credit_referral(c, 1000, &config).unwrap();
+        sp.credit_referral(b, 2000, &config).unwrap();
+        sp.credit_referral(a, 3000, &config).unwrap();
+        assert_eq!(sp.referral_credited_pointer(a).get_value::<u128>(), 50 + 200);
+        assert_eq!(sp.referral_credited_pointer(b).get_value::<u128>(), 100);
+        assert_eq!(sp.referral_credited_pointer(c).get_value::<u128>(), 0);
+        assert_eq!(sp.referral_credited_total_pointer().get_value::<u128>(), total + 350);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_brc20_pools(){
+        let sp = StakingPool::default();
+        let ordi = own_pool(&sp, "ordi", 1000000);
+        let pepe = own_pool(&sp, "pepe", 3000000);
+        let count = sp.get_orbital_count();
+
+        //独立排放的权重池互不影响
+        let staking_height = 455;
+        let (a, b) = (count + 1, count + 2);
+        for (index, brc20) in [(a, ordi), (b, pepe)] {
+            let staking = new_staking(brc20, 50000, staking_height, 0, 400 + index);
+            sp.add_staking(index, &staking, Decimal::ONE, 0);
+        }
+
+        let height = staking_height as u128 + 144 * 10;
+        let (p,r,w) = sp.calc_profit(a, height).unwrap();
+        assert_eq!(p, 10 * 1000000);
+        assert_eq!((p,r,w), sp.calc_profit_1(a, height).unwrap());
+        let (p,r,w) = sp.calc_profit(b, height).unwrap();
+        assert_eq!(p, 10 * 3000000);
+        assert_eq!((p,r,w), sp.calc_profit_1(b, height).unwrap());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_accumulator(){
+        let sp = StakingPool::default();
+        let brc20 = own_pool(&sp, "accu", 1000000);
+        let pool = sp.weight_pool(brc20);
+        let count = sp.get_orbital_count();
+        let config = sp.config();
+
+        //a 第 0 天质押，b 第 3 天质押，第 5 天结算后 c 补登第 1 天的质押，从第 5 天开始计算
+        let (a, b, c) = (count + 1, count + 2, count + 3);
+        sp.add_staking(a, &new_staking(brc20, 50000, 455, 0, 500 + a), Decimal::ONE, 0);
+        sp.add_staking(b, &new_staking(brc20, 150000, 455 + 144 * 3, 0, 500 + b), Decimal::from_str("1.5").unwrap(), 3);
+        sp.add_staking(c, &new_staking(brc20, 100000, 455 + 144, 0, 500 + c), Decimal::ONE, 5);
+        assert_eq!(sp.get_acc(pool).day, 5);
+        assert_eq!(sp.get_staking_start_day(c, &sp.get_staking(c), &sp.config()), 5);
+
+        for height in [455u128, 455 + 144 * 2, 455 + 144 * 7, 455 + 144 * 29, 455 + 144 * 40, 455 + 144 * 250] {
+            for index in [a, b, c] {
+                assert_eq!(sp.calc_profit(index, height).unwrap(), sp.calc_profit_1(index, height).unwrap());
+            }
+        }
+
+        //只有 a 的前三天独占排放
+        let (p,r,_) = sp.calc_profit(a, 455 + 144 * 3).unwrap();
+        assert_eq!(p, 3 * 1000000);
+        assert_eq!(r, 1000000 * (2 + 1 + 0) / config.profit_release_day as u128);
+
+        //推算的累加值与结算后一致
+        let before = sp.get_acc_at(pool, 20, &config);
+        sp.accumulate(pool, 30);
+        assert_eq!(sp.get_acc_at(pool, 20, &config), before);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_day_bonus(){
+        let sp = StakingPool::default();
+        let brc20 = own_pool(&sp, "bons", 1000000);
+        let pool = sp.weight_pool(brc20);
+        let count = sp.get_orbital_count();
+
+        let (a, b) = (count + 1, count + 2);
+        //第 1 天 a 独占重新分配的收益，第 2 天按权重分
+        sp.add_staking(a, &new_staking(brc20, 50000, 455, 0, 600 + a), Decimal::ONE, 0);
+        sp.add_day_bonus(pool, 1, 400000);
+        sp.add_staking(b, &new_staking(brc20, 150000, 455 + 144 * 2, 0, 600 + b), Decimal::ONE, 2);
+        sp.add_day_bonus(pool, 2, 400000);
+
+        let height = 455 + 144 * 3;
+        assert_eq!(sp.calc_profit(a, height).unwrap().0, 1000000 * 2 + 400000 + 1400000 / 4);
+        assert_eq!(sp.calc_profit(b, height).unwrap().0, 1400000 * 3 / 4);
+        for index in [a, b] {
+            assert_eq!(sp.calc_profit(index, height).unwrap(), sp.calc_profit_1(index, height).unwrap());
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_reweight(){
+        let sp = StakingPool::default();
+        let brc20 = own_pool(&sp, "rewt", 1200000);
+        let count = sp.get_orbital_count();
+
+        //a 第 3 天权重翻倍并延长到 90 天，b 不变
+        let (a, b) = (count + 1, count + 2);
+        sp.add_staking(a, &new_staking(brc20, 50000, 455, 0, 700 + a), Decimal::ONE, 0);
+        sp.add_staking(b, &new_staking(brc20, 50000, 455, 0, 700 + b), Decimal::ONE, 0);
+        let mut staking = sp.get_staking(a);
+        staking.period = 90;
+        sp.reweight_staking(a, Decimal::from(100000), sp.expire_day(&staking), 3);
+        sp.set_staking(a, &staking);
+        assert_eq!(sp.get_weight_segments(a, &staking, &sp.config()).len(), 2);
+
+        let (p,_,_) = sp.calc_profit(a, 455 + 144 * 5).unwrap();
+        assert_eq!(p, 600000 * 3 + 800000 * 2);
+        //b 在第 30 天到期后 a 独占，a 在第 90 天到期
+        let (p,_,_) = sp.calc_profit(a, 455 + 144 * 100).unwrap();
+        assert_eq!(p, 600000 * 3 + 800000 * 27 + 1200000 * 60);
+        for height in [455u128, 455 + 144 * 4, 455 + 144 * 31, 455 + 144 * 200, 455 + 144 * 400] {
+            for index in [a, b] {
+                assert_eq!(sp.calc_profit(index, height).unwrap(), sp.calc_profit_1(index, height).unwrap());
+            }
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_auto_renew(){
+        let sp = StakingPool::default();
+        let brc20 = own_pool(&sp, "renw", 1200000);
+        let count = sp.get_orbital_count();
+
+        //a 自动续期，b 第 30 天到期
+        let (a, b) = (count + 1, count + 2);
+        sp.add_staking(a, &new_staking(brc20, 50000, 455, 0, 800 + a), Decimal::ONE, 0);
+        sp.add_staking(b, &new_staking(brc20, 50000, 455, 0, 800 + b), Decimal::ONE, 0);
+        sp.renew_staking(a, true, 455 + 144 * 3).unwrap();
+        assert_eq!(sp.expire_day(&sp.get_staking(a)), None);
+
+        let (p,_,_) = sp.calc_profit(a, 455 + 144 * 40).unwrap();
+        assert_eq!(p, 600000 * 30 + 1200000 * 10);
+
+        //第 40 天关闭后在第二个周期结束时到期
+        sp.renew_staking(a, false, 455 + 144 * 40).unwrap();
+        let staking = sp.get_staking(a);
+        assert_eq!(staking.renewals, 1);
+        assert_eq!(staking.get_expire_height(), 455 + 144 * 60);
+        let (p,_,_) = sp.calc_profit(a, 455 + 144 * 100).unwrap();
+        assert_eq!(p, 600000 * 30 + 1200000 * 30);
+        for height in [455u128, 455 + 144 * 31, 455 + 144 * 59, 455 + 144 * 61, 455 + 144 * 400] {
+            for index in [a, b] {
+                assert_eq!(sp.calc_profit(index, height).unwrap(), sp.calc_profit_1(index, height).unwrap());
+            }
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_pause(){
+        let sp = StakingPool::default();
+        assert!(sp.check_paused(54).is_err());
+        sp.schema_version_pointer().set_value::<u32>(SCHEMA_VERSION);
+        assert!(sp.check_paused(54).is_ok());
+        sp.paused_pointer().set(Arc::new(Staking::serialize_invite_vec(&vec![54]).unwrap()));
+        assert!(sp.check_paused(54).is_err());
+        assert!(sp.check_paused(51).is_ok());
+
+        sp.emergency_pointer().set_value::<u8>(1);
+        assert!(sp.check_paused(51).is_err());
+
+        sp.emergency_pointer().set_value::<u8>(0);
+        sp.paused_pointer().set(Arc::new(vec![]));
+        assert!(sp.check_paused(54).is_ok());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_migrate_staking(){
+        let sp = StakingPool::default();
+        //同样的质押在另一个池中按当前格式登记
+        let current = own_pool(&sp, "curr", 1200000);
+        let twin = sp.get_orbital_count() + 1;
+        sp.add_staking(twin, &new_staking(current, 50000, 455, 0, 900 + twin), Decimal::from(2), 0);
+        let before = sp.calc_profit(twin, 455 + 144 * 10).unwrap();
+
+        //旧版本的记录：没有版本前缀，权重以字符串保存，没有开始计算收益的天，也没有权重变化
+        let brc20 = own_pool(&sp, "migr", 1200000);
+        let index = sp.get_orbital_count() + 1;
+        let staking = new_staking(brc20, 50000, 455, 0, 900 + index);
+        let raw = Staking::serialize(&staking).unwrap()[3..].to_vec();
+        sp.staking_pointer(index).set(Arc::new(raw));
+        sp.staking_w_pointer(index).set(Arc::new(b"2".to_vec()));
+        sp.set_orbital_count(index);
+        assert_eq!(sp.get_weight_delta(brc20, 0), Decimal::ZERO);
+
+        sp.migrate_staking(index, &sp.config());
+        assert_eq!(sp.get_weight_delta(brc20, 0), Decimal::from(100000));
+        assert_eq!(sp.get_weight_delta(brc20, 30), Decimal::from(-100000));
+        assert_eq!(Staking::encoding_version(&sp.staking_pointer(index).get()), STAKING_VERSION);
+        assert_eq!(sp.staking_w_pointer(index).get().len(), 16);
+        assert_eq!(sp.staking_start_day_pointer(index).get().len(), 8);
+        assert_eq!(sp.staking_ratio_pointer(index).get().len(), 16);
+        assert_eq!(sp.get_staking_ratio(index), Decimal::ONE);
+        assert_eq!(sp.get_staking(index), staking);
+        assert_eq!(sp.calc_profit(index, 455 + 144 * 10).unwrap(), before);
+
+        //再次迁移不重复计入权重
+        sp.staking_start_day_pointer(index).set(Arc::new(vec![]));
+        sp.migrate_staking(index, &sp.config());
+        assert_eq!(sp.get_weight_delta(brc20, 0), Decimal::from(100000));
+        assert_eq!(sp.get_weight_delta(brc20, 30), Decimal::from(-100000));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_emitted_dust(){
+        let sp = StakingPool::default();
+        let config = sp.config();
+        let brc20 = own_pool(&sp, "dust", 100);
+        let count = sp.get_orbital_count();
+
+        //第 2 天才有质押，之前的排放不计入
+        let (a, b) = (count + 1, count + 2);
+        sp.add_staking(a, &new_staking(brc20, 1, 455 + 144 * 2, 0, 1000 + a), Decimal::ONE, 2);
+        sp.add_staking(b, &new_staking(brc20, 2, 455 + 144 * 2, 0, 1000 + b), Decimal::ONE, 2);
+        let height = 455 + 144 * 12;
+        let emitted = sp.get_emitted_at(brc20, 12, &config);
+        assert_eq!(emitted, 100 * 10);
+
+        let allocated = sp.calc_profit(a, height).unwrap().0 + sp.calc_profit(b, height).unwrap().0;
+        assert!(allocated < emitted && emitted - allocated <= 2);
+
+        sp.accumulate(brc20, 5);
+        assert_eq!(sp.emitted_pointer(brc20).get_value::<u128>(), 100 * 3);
+        assert_eq!(sp.get_emitted_at(brc20, 12, &config), emitted);
+        assert!(sp.weight_pools().contains(&brc20));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_settle_reward(){
+        let sp = StakingPool::default();
+        let brc20 = own_pool(&sp, "settle", 100);
+        let count = sp.get_orbital_count();
+        let (a, b) = (count + 1, count + 2);
+        for (index, value) in [(a, 1), (b, 2)] {
+            let mut staking = new_staking(brc20, value, 455 + 144 * 2, 0, 1100 + index);
+            staking.period = 10;
+            sp.add_staking(index, &staking, Decimal::ONE, 2);
+        }
+        let allocated = sp.reward_allocated_pointer().get_value::<u128>();
+        let dust = sp.get_reward_dust();
+
+        //挖矿期间只更新已分配的收益
+        sp.settle_reward(a, 455 + 144 * 5).unwrap();
+        assert_eq!(sp.reward_allocated_pointer().get_value::<u128>(), allocated + 100);
+        assert_eq!(sp.get_reward_dust(), dust);
+
+        //到期后两个质押舍去的部分合起来是一个单位
+        let height = 455 + 144 * 20;
+        sp.settle_reward(a, height).unwrap();
+        sp.settle_reward(b, height).unwrap();
+        let mined = sp.calc_profit(a, height as u128).unwrap().0 + sp.calc_profit(b, height as u128).unwrap().0;
+        assert_eq!(mined, 999);
+        assert_eq!(sp.reward_allocated_pointer().get_value::<u128>(), allocated + mined);
+        let recorded = sp.get_reward_dust() - dust;
+        assert!(recorded > Decimal::from_str("0.99").unwrap() && recorded <= Decimal::ONE);
+
+        //再次领取不会重复记录
+        sp.settle_reward(b, height).unwrap();
+        assert_eq!(sp.get_reward_dust() - dust, recorded);
+    }
+}
@@ -0,0 +1,31 @@
+/// Largest decimals whose unit fits in a u128
+pub const MAX_DECIMALS: u8 = 38;
+
+/// Human readable `value` of a token with `decimals`, trailing zeros trimmed
+pub fn format_amount(value: u128, decimals: u8) -> String {
+    let decimals = decimals.min(MAX_DECIMALS) as usize;
+    let unit = 10u128.pow(decimals as u32);
+    let fraction = value % unit;
+    if fraction == 0 {
+        return (value / unit).to_string();
+    }
+    let fraction = format!("{:0width$}", fraction, width = decimals);
+    format!("{}.{}", value / unit, fraction.trim_end_matches('0'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    fn test_format_amount() {
+        assert_eq!(format_amount(100000000000000000, 8), "1000000000");
+        assert_eq!(format_amount(144444444444444, 8), "1444444.44444444");
+        assert_eq!(format_amount(1, 8), "0.00000001");
+        assert_eq!(format_amount(1500, 3), "1.5");
+        assert_eq!(format_amount(0, 18), "0");
+        assert_eq!(format_amount(42, 0), "42");
+        assert_eq!(format_amount(u128::MAX, 38), "3.40282366920938463463374607431768211455");
+    }
+}
@@ -0,0 +1,463 @@
+use crate::amount::MAX_DECIMALS;
+use crate::emission::EmissionSchedule;
+use crate::proof::BRC20_DECIMALS;
+use anyhow::{anyhow, Result};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// 每天的区块数
+pub const BLOCKS_PER_DAY: u64 = 144;
+
+/// alkane 的默认精度，白名单没有登记精度时使用
+pub const ALKANE_DECIMALS: u8 = 8;
+
+/// 推荐奖励最多计算的邀请层级
+pub const MAX_REFERRAL_LEVELS: usize = 3;
+
+/// Weight multiplier of a lock period in days
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeriodWeight {
+    pub period: u16,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub weight: Decimal,
+}
+
+impl PeriodWeight {
+    pub fn new(period: u16, weight: &str) -> Self {
+        PeriodWeight { period, weight: Decimal::from_str(weight).unwrap() }
+    }
+}
+
+/// A stakeable BRC-20 ticker.
+///
+/// Tickers without their own `day_volume` share the pool's emission with ticker 0,
+/// their stakes weighted by `ratio` to normalize prices. Tickers with a `day_volume`
+/// get a separate emission budget and weight pool. `decimals` is the precision of the
+/// ticker's deploy inscription, staked amounts are stored with BRC20_DECIMALS either way.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Brc20Ticker {
+    pub name: String,
+    pub ratio: Decimal,
+    pub day_volume: u128,
+    pub decimals: u8,
+}
+
+impl Default for Brc20Ticker {
+    fn default() -> Self {
+        Brc20Ticker { name: String::new(), ratio: Decimal::ONE, day_volume: 0, decimals: BRC20_DECIMALS as u8 }
+    }
+}
+
+impl Brc20Ticker {
+    pub fn from_vec8(data: &[u8]) -> Result<Self> {
+        let ticker: Brc20Ticker =
+            serde_json::from_slice(data).map_err(|e| anyhow!("invalid brc20 ticker: {}", e))?;
+        if ticker.name.is_empty() || ticker.name.len() > 32 {
+            return Err(anyhow!("invalid brc20 ticker name"));
+        }
+        if ticker.ratio <= Decimal::ZERO {
+            return Err(anyhow!("brc20 ratio must be positive"));
+        }
+        if ticker.decimals as u32 > BRC20_DECIMALS {
+            return Err(anyhow!("brc20 decimals must be at most {}", BRC20_DECIMALS));
+        }
+        Ok(ticker)
+    }
+
+    pub fn to_vec8(&self) -> Result<Vec<u8>> {
+        serde_json::to_vec(self).map_err(|e| anyhow!("serialize error:{}", e))
+    }
+}
+
+/// An alkane token accepted for staking, its stakes weighted by `ratio`.
+///
+/// `decimals` is only used to display amounts, the token itself is not queried for it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StakeToken {
+    pub id: [u128; 2],
+    pub ratio: Decimal,
+    pub decimals: u8,
+}
+
+impl Default for StakeToken {
+    fn default() -> Self {
+        StakeToken { id: [0, 0], ratio: Decimal::ONE, decimals: ALKANE_DECIMALS }
+    }
+}
+
+pub fn validate_token_whitelist(tokens: &[StakeToken]) -> Result<()> {
+    for (i, token) in tokens.iter().enumerate() {
+        if token.id == [0, 0] || token.ratio <= Decimal::ZERO {
+            return Err(anyhow!("token id must be set and ratio positive"));
+        }
+        if token.decimals > MAX_DECIMALS {
+            return Err(anyhow!("token decimals must be at most {}", MAX_DECIMALS));
+        }
+        if tokens[..i].iter().any(|other| other.id == token.id) {
+            return Err(anyhow!("duplicate token {}:{}", token.id[0], token.id[1]));
+        }
+    }
+    Ok(())
+}
+
+/// Where the rewards forfeited by an early unstake go
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PenaltyDestination {
+    /// Burned through the coin contract, leaving the total supply
+    Burn,
+    /// Added to the emission of the unstaking day for the remaining stakers
+    Redistribute,
+    /// Withdrawn by the holder of the pool's treasury token
+    Treasury,
+}
+
+/// Penalty of an unstake before the lock period expires.
+///
+/// `reward_rate` of the rewards mined but not yet released is forfeited, the rest is
+/// released at once. `principal_rate` of the BRC-20 is withheld from the redemption.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EarlyUnstakePenalty {
+    #[serde(default)]
+    pub reward_rate: Decimal,
+    #[serde(default)]
+    pub principal_rate: Decimal,
+    pub destination: PenaltyDestination,
+}
+
+/// Parameters of a staking pool campaign, set once by `Initialize`.
+///
+/// Missing JSON fields fall back to the values of the original pool.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PoolConfig {
+    /// coin 总量，全部由质押池持有
+    pub cap: u128,
+    /// 每天挖出的 coin，没有配置 emission 时按此固定排放
+    pub mining_one_day_volume: u128,
+    /// 排放曲线
+    pub emission: Option<EmissionSchedule>,
+    /// 挖矿排放总量上限
+    pub mining_cap: u128,
+    /// 挖矿的第一个块高度
+    pub mining_first_height: u64,
+    /// 挖矿的最后块高度
+    pub mining_last_height: u64,
+    pub min_staking_value: u128,
+    /// 收益线性释放天数
+    pub profit_release_day: u64,
+    pub coin_template_id: u128,
+    pub orbital_template_id: u128,
+    pub coin_name: String,
+    pub coin_symbol: String,
+    /// 允许的质押周期及其权重倍数
+    pub period_weights: Vec<PeriodWeight>,
+    /// 每一级邀请人获得被邀请人已释放收益的比例，空表示不发放推荐奖励
+    pub referral_rates: Vec<Decimal>,
+    /// 提前解质押的惩罚，空表示不惩罚
+    pub early_unstake_penalty: Option<EarlyUnstakePenalty>,
+    /// 可以提取国库资金的 alkane
+    pub treasury: Option<[u128; 2]>,
+    /// coin 复投质押的权重倍数，与周期权重相乘
+    pub compound_weight: Decimal,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            cap: 100000000000000000,
+            mining_one_day_volume: 144444444444444,
+            emission: None,
+            mining_cap: 52000000000000000,
+            mining_first_height: 450,
+            mining_last_height: 450 + BLOCKS_PER_DAY * 360 - 1,
+            min_staking_value: 1000,
+            profit_release_day: 180,
+            coin_template_id: 3,
+            orbital_template_id: 1,
+            coin_name: String::from("Alkanes Forge"),
+            coin_symbol: String::from("forge"),
+            period_weights: vec![
+                PeriodWeight::new(30, "1.0"),
+                PeriodWeight::new(90, "1.5"),
+                PeriodWeight::new(180, "1.8"),
+                PeriodWeight::new(360, "2.2"),
+            ],
+            referral_rates: vec![],
+            early_unstake_penalty: None,
+            treasury: None,
+            compound_weight: Decimal::ONE,
+        }
+    }
+}
+
+impl PoolConfig {
+    /// Parses a JSON config, an empty payload gives the default config
+    pub fn from_vec8(data: &[u8]) -> Result<Self> {
+        let config = PoolConfig::decode(data)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Decodes a config that was validated when it was stored
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.is_empty() {
+            return Ok(PoolConfig::default());
+        }
+        serde_json::from_slice(data).map_err(|e| anyhow!("invalid pool config: {}", e))
+    }
+
+    pub fn to_vec8(&self) -> Result<Vec<u8>> {
+        serde_json::to_vec(self).map_err(|e| anyhow!("serialize error:{}", e))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.mining_last_height < self.mining_first_height {
+            return Err(anyhow!("mining ends before it starts"));
+        }
+        if (self.emission.is_none() && self.mining_one_day_volume == 0) || self.profit_release_day == 0 {
+            return Err(anyhow!("mining volume and release days must be positive"));
+        }
+        if self.coin_name.len() > 32 || self.coin_symbol.len() > 16 {
+            return Err(anyhow!("coin name or symbol too long"));
+        }
+        validate_period_weights(&self.period_weights)?;
+        if self.referral_rates.len() > MAX_REFERRAL_LEVELS {
+            return Err(anyhow!("at most {} referral levels", MAX_REFERRAL_LEVELS));
+        }
+        if self.referral_rates.iter().any(|rate| *rate < Decimal::ZERO)
+            || self.referral_rates.iter().sum::<Decimal>() > Decimal::ONE
+        {
+            return Err(anyhow!("referral rates must be positive and sum to at most 1"));
+        }
+        if let Some(penalty) = &self.early_unstake_penalty {
+            let rates = [penalty.reward_rate, penalty.principal_rate];
+            if rates.iter().any(|rate| *rate < Decimal::ZERO || *rate > Decimal::ONE) {
+                return Err(anyhow!("penalty rates must be between 0 and 1"));
+            }
+            if penalty.destination == PenaltyDestination::Treasury && self.treasury.is_none() {
+                return Err(anyhow!("penalty sent to an unset treasury"));
+            }
+        }
+        if self.compound_weight <= Decimal::ZERO {
+            return Err(anyhow!("compound weight must be positive"));
+        }
+        if self.mining_cap > self.cap {
+            return Err(anyhow!("mining cap exceeds cap"));
+        }
+        self.emission().validate(self.mining_days(), self.emission_budget())
+    }
+
+    /// Share of the claimed mining rewards paid on top of them as referral rewards
+    pub fn referral_share(&self) -> Decimal {
+        self.referral_rates.iter().sum()
+    }
+
+    /// Part of the mining cap the emission may use, the rest is set aside for the
+    /// referral rewards
+    pub fn emission_budget(&self) -> u128 {
+        (Decimal::from(self.mining_cap) / (Decimal::ONE + self.referral_share()))
+            .floor()
+            .try_into()
+            .unwrap_or(0)
+    }
+
+    /// `emission` plus the referral rewards paid on top of it
+    pub fn with_referral(&self, emission: u128) -> Result<u128> {
+        let referral: u128 = (Decimal::from(emission) * self.referral_share()).floor().try_into()?;
+        emission
+            .checked_add(referral)
+            .ok_or_else(|| anyhow!("emission overflow"))
+    }
+
+    pub fn emission(&self) -> EmissionSchedule {
+        self.emission.clone().unwrap_or(EmissionSchedule::Flat {
+            day_volume: self.mining_one_day_volume,
+        })
+    }
+
+    /// Coins mined on day `day`
+    pub fn day_volume(&self, day: u64) -> u128 {
+        self.emission().day_volume(day, self.mining_days())
+    }
+
+    /// Weight multiplier of `period`, None if the period can't be staked
+    pub fn period_weight(&self, period: u16) -> Option<Decimal> {
+        self.period_weights
+            .iter()
+            .find(|w| w.period == period)
+            .map(|w| w.weight)
+    }
+
+    /// Number of (possibly partial) mining days
+    pub fn mining_days(&self) -> u64 {
+        (self.mining_last_height - self.mining_first_height) / BLOCKS_PER_DAY + 1
+    }
+
+    /// Day number of `height` counted from the first mining height, 0 before it
+    pub fn height_to_no(&self, height: u64) -> u64 {
+        height.saturating_sub(self.mining_first_height) / BLOCKS_PER_DAY
+    }
+}
+
+pub fn validate_period_weights(weights: &[PeriodWeight]) -> Result<()> {
+    if weights.is_empty() {
+        return Err(anyhow!("empty period table"));
+    }
+    for (i, w) in weights.iter().enumerate() {
+        if w.period == 0 || w.weight <= Decimal::ZERO {
+            return Err(anyhow!("period and weight must be positive"));
+        }
+        if weights[..i].iter().any(|other| other.period == w.period) {
+            return Err(anyhow!("duplicate period {}", w.period));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    fn test_config() {
+        let config = PoolConfig::from_vec8(&[]).unwrap();
+        assert_eq!(config, PoolConfig::default());
+        assert_eq!(config.mining_days(), 360);
+        assert_eq!(config.height_to_no(450 + 144), 1);
+
+        let config = PoolConfig::from_vec8(
+            br#"{"mining_first_height":1000,"mining_last_height":1143,"coin_name":"Test"}"#,
+        )
+        .unwrap();
+        assert_eq!(config.mining_days(), 1);
+        assert_eq!(config.coin_name, "Test");
+        assert_eq!(config.cap, PoolConfig::default().cap);
+        assert_eq!(PoolConfig::from_vec8(&config.to_vec8().unwrap()).unwrap(), config);
+
+        assert!(PoolConfig::from_vec8(br#"{"mining_last_height":10}"#).is_err());
+        assert!(PoolConfig::from_vec8(br#"{"cap":1}"#).is_err());
+        assert!(PoolConfig::from_vec8(br#"{"compound_weight":"0"}"#).is_err());
+        assert!(PoolConfig::from_vec8(b"not json").is_err());
+
+        //读取已保存的配置时不再校验
+        assert_eq!(PoolConfig::decode(br#"{"cap":1}"#).unwrap().cap, 1);
+        assert_eq!(PoolConfig::decode(&[]).unwrap(), PoolConfig::default());
+        assert!(PoolConfig::decode(b"not json").is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_emission() {
+        let config = PoolConfig::default();
+        assert_eq!(config.day_volume(0), 144444444444444);
+        assert_eq!(config.day_volume(360), 0);
+
+        let config = PoolConfig::from_vec8(
+            br#"{"mining_cap":1000,"emission":{"step":{"initial":100,"interval_days":1,"ratio":"0.5"}}}"#,
+        )
+        .unwrap();
+        assert_eq!(config.day_volume(1), 50);
+        assert!(PoolConfig::from_vec8(br#"{"mining_cap":1000}"#).is_err());
+        assert!(PoolConfig::from_vec8(br#"{"cap":1000,"mining_cap":1001,"emission":{"flat":{"day_volume":0}}}"#).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_brc20_ticker() {
+        let ticker = Brc20Ticker::from_vec8(br#"{"name":"ordi"}"#).unwrap();
+        assert_eq!(ticker.ratio, Decimal::ONE);
+        assert_eq!(ticker.day_volume, 0);
+        assert_eq!(ticker.decimals, 18);
+
+        let ticker = Brc20Ticker::from_vec8(br#"{"name":"ordi","ratio":"2.5","day_volume":1000}"#).unwrap();
+        assert_eq!(ticker.ratio, Decimal::from_str("2.5").unwrap());
+        assert_eq!(Brc20Ticker::from_vec8(&ticker.to_vec8().unwrap()).unwrap(), ticker);
+
+        assert!(Brc20Ticker::from_vec8(br#"{"ratio":"1"}"#).is_err());
+        assert!(Brc20Ticker::from_vec8(br#"{"name":"ordi","ratio":"0"}"#).is_err());
+        assert_eq!(Brc20Ticker::from_vec8(br#"{"name":"sats","decimals":8}"#).unwrap().decimals, 8);
+        assert!(Brc20Ticker::from_vec8(br#"{"name":"ordi","decimals":19}"#).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_referral_rates() {
+        let config = PoolConfig::from_vec8(br#"{"referral_rates":["0.1","0.05"],"mining_cap":60000000000000000}"#).unwrap();
+        assert_eq!(config.referral_rates, vec![Decimal::from_str("0.1").unwrap(), Decimal::from_str("0.05").unwrap()]);
+        assert!(PoolConfig::default().referral_rates.is_empty());
+        assert_eq!(config.emission_budget(), 52173913043478260);
+        assert_eq!(config.with_referral(1000).unwrap(), 1150);
+
+        // the referral rewards must fit in the mining cap next to the emission
+        assert!(PoolConfig::from_vec8(br#"{"referral_rates":["0.1"]}"#).is_err());
+
+        assert!(PoolConfig::from_vec8(br#"{"referral_rates":["0.6","0.5"]}"#).is_err());
+        assert!(PoolConfig::from_vec8(br#"{"referral_rates":["-0.1"]}"#).is_err());
+        assert!(PoolConfig::from_vec8(br#"{"referral_rates":["0.1","0.1","0.1","0.1"]}"#).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_token_whitelist() {
+        let tokens: Vec<StakeToken> =
+            serde_json::from_slice(br#"[{"id":[2,9]},{"id":[2,10],"ratio":"0.5","decimals":6}]"#).unwrap();
+        assert_eq!(tokens[0].ratio, Decimal::ONE);
+        assert_eq!(tokens[0].decimals, ALKANE_DECIMALS);
+        assert_eq!(tokens[1].ratio, Decimal::from_str("0.5").unwrap());
+        assert_eq!(tokens[1].decimals, 6);
+        assert!(validate_token_whitelist(&tokens).is_ok());
+        assert!(validate_token_whitelist(&[]).is_ok());
+
+        let duplicate = vec![tokens[0].clone(), tokens[0].clone()];
+        assert!(validate_token_whitelist(&duplicate).is_err());
+        assert!(validate_token_whitelist(&[StakeToken::default()]).is_err());
+        assert!(validate_token_whitelist(&[StakeToken { id: [2, 9], ratio: Decimal::ZERO, decimals: 8 }]).is_err());
+        assert!(validate_token_whitelist(&[StakeToken { id: [2, 9], ratio: Decimal::ONE, decimals: 39 }]).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_early_unstake_penalty() {
+        let config = PoolConfig::from_vec8(
+            br#"{"early_unstake_penalty":{"reward_rate":"0.5","destination":"redistribute"}}"#,
+        )
+        .unwrap();
+        let penalty = config.early_unstake_penalty.unwrap();
+        assert_eq!(penalty.reward_rate, Decimal::from_str("0.5").unwrap());
+        assert_eq!(penalty.principal_rate, Decimal::ZERO);
+        assert_eq!(penalty.destination, PenaltyDestination::Redistribute);
+
+        let config = PoolConfig::from_vec8(
+            br#"{"treasury":[2,9],"early_unstake_penalty":{"principal_rate":"0.1","destination":"treasury"}}"#,
+        )
+        .unwrap();
+        assert_eq!(config.treasury, Some([2, 9]));
+
+        assert!(PoolConfig::from_vec8(br#"{"early_unstake_penalty":{"destination":"treasury"}}"#).is_err());
+        assert!(PoolConfig::from_vec8(
+            br#"{"early_unstake_penalty":{"reward_rate":"1.5","destination":"burn"}}"#
+        )
+        .is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_period_weights() {
+        let config = PoolConfig::default();
+        assert_eq!(config.period_weight(90), Some(Decimal::from_str("1.5").unwrap()));
+        assert_eq!(config.period_weight(60), None);
+
+        let config = PoolConfig::from_vec8(
+            br#"{"period_weights":[{"period":60,"weight":"1.2"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(config.period_weight(60), Some(Decimal::from_str("1.2").unwrap()));
+        assert_eq!(config.period_weight(30), None);
+
+        assert!(PoolConfig::from_vec8(br#"{"period_weights":[]}"#).is_err());
+        assert!(PoolConfig::from_vec8(
+            br#"{"period_weights":[{"period":60,"weight":"1.2"},{"period":60,"weight":"1.5"}]}"#
+        )
+        .is_err());
+        assert!(PoolConfig::from_vec8(br#"{"period_weights":[{"period":60,"weight":"0"}]}"#).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+use anyhow::{anyhow, Result};
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::{Decimal, MathematicalOps};
+use serde::{Deserialize, Serialize};
+
+/// Volume starting at `from_day` of a piecewise emission table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmissionStep {
+    pub from_day: u64,
+    pub day_volume: u128,
+}
+
+/// Coins mined per day over the mining days of a pool.
+///
+/// Day numbers count from the first mining height, nothing is emitted at or after
+/// `days`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmissionSchedule {
+    /// The same volume every day
+    Flat { day_volume: u128 },
+    /// Volume multiplied by `ratio` (at most 1) every `interval_days`, a halving is ratio 0.5
+    Step {
+        initial: u128,
+        interval_days: u64,
+        ratio: Decimal,
+    },
+    /// Volume moving linearly from `initial` on the first day to `last` on the last day
+    LinearDecay { initial: u128, last: u128 },
+    /// Volume of the last step starting at or before the day, 0 before the first step
+    Table { steps: Vec<EmissionStep> },
+}
+
+impl EmissionSchedule {
+    /// Coins mined on `day` of a schedule lasting `days`
+    pub fn day_volume(&self, day: u64, days: u64) -> u128 {
+        if day >= days {
+            return 0;
+        }
+        match self {
+            EmissionSchedule::Flat { day_volume } => *day_volume,
+            EmissionSchedule::Step {
+                initial,
+                interval_days,
+                ratio,
+            } => ratio
+                .checked_powu(day / interval_days)
+                .zip(Decimal::from_u128(*initial))
+                .and_then(|(factor, initial)| factor.checked_mul(initial))
+                .map_or(0, |v| v.floor().try_into().unwrap_or(0)),
+            EmissionSchedule::LinearDecay { initial, last } => {
+                if days < 2 {
+                    return *initial;
+                }
+                let (Some(initial_d), Some(last_d)) =
+                    (Decimal::from_u128(*initial), Decimal::from_u128(*last))
+                else {
+                    return 0;
+                };
+                (last_d - initial_d)
+                    .checked_mul(Decimal::from(day))
+                    .and_then(|v| v.checked_div(Decimal::from(days - 1)))
+                    .and_then(|v| v.checked_add(initial_d))
+                    .map_or(0, |v| v.floor().try_into().unwrap_or(0))
+            }
+            EmissionSchedule::Table { steps } => steps
+                .iter()
+                .take_while(|step| step.from_day <= day)
+                .last()
+                .map_or(0, |step| step.day_volume),
+        }
+    }
+
+    /// Total emission of a schedule lasting `days`
+    pub fn total(&self, days: u64) -> Result<u128> {
+        (0..days).try_fold(0u128, |total, day| {
+            total
+                .checked_add(self.day_volume(day, days))
+                .ok_or_else(|| anyhow!("emission overflow"))
+        })
+    }
+
+    pub fn validate(&self, days: u64, mining_cap: u128) -> Result<()> {
+        // Decimal 只能表示 7.9e28 以内的数量
+        match self {
+            EmissionSchedule::Step {
+                initial,
+                interval_days,
+                ratio,
+            } if *interval_days == 0
+                || *ratio < Decimal::ZERO
+                || *ratio > Decimal::ONE
+                || Decimal::from_u128(*initial).is_none() =>
+            {
+                return Err(anyhow!("invalid step emission"));
+            }
+            EmissionSchedule::LinearDecay { initial, last }
+                if Decimal::from_u128(*initial).is_none() || Decimal::from_u128(*last).is_none() =>
+            {
+                return Err(anyhow!("invalid linear emission"));
+            }
+            EmissionSchedule::Table { steps }
+                if steps.windows(2).any(|w| w[0].from_day >= w[1].from_day) =>
+            {
+                return Err(anyhow!("emission table must be sorted by day"));
+            }
+            _ => {}
+        }
+        if self.total(days)? > mining_cap {
+            return Err(anyhow!("emission exceeds mining cap"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    fn test_flat() {
+        let schedule = EmissionSchedule::Flat { day_volume: 100 };
+        assert_eq!(schedule.day_volume(0, 10), 100);
+        assert_eq!(schedule.day_volume(9, 10), 100);
+        assert_eq!(schedule.day_volume(10, 10), 0);
+        assert_eq!(schedule.total(10).unwrap(), 1000);
+        assert!(schedule.validate(10, 1000).is_ok());
+        assert!(schedule.validate(10, 999).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_halving() {
+        let schedule = EmissionSchedule::Step {
+            initial: 1000,
+            interval_days: 3,
+            ratio: Decimal::from_str("0.5").unwrap(),
+        };
+        assert_eq!(schedule.day_volume(2, 10), 1000);
+        assert_eq!(schedule.day_volume(3, 10), 500);
+        assert_eq!(schedule.day_volume(9, 10), 125);
+        assert_eq!(schedule.total(10).unwrap(), 3000 + 1500 + 750 + 125);
+
+        let growing = EmissionSchedule::Step {
+            initial: 1000,
+            interval_days: 1,
+            ratio: Decimal::from(2),
+        };
+        assert!(growing.validate(1000, u128::MAX).is_err());
+
+        let huge = EmissionSchedule::Step {
+            initial: u128::MAX,
+            interval_days: 1,
+            ratio: Decimal::ONE,
+        };
+        assert!(huge.validate(1000, u128::MAX).is_err());
+        assert_eq!(huge.day_volume(0, 1000), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_linear_decay() {
+        let schedule = EmissionSchedule::LinearDecay {
+            initial: 1000,
+            last: 100,
+        };
+        assert_eq!(schedule.day_volume(0, 11), 1000);
+        assert_eq!(schedule.day_volume(5, 11), 550);
+        assert_eq!(schedule.day_volume(10, 11), 100);
+        assert_eq!(schedule.total(11).unwrap(), 6050);
+
+        let huge = EmissionSchedule::LinearDecay {
+            initial: u128::MAX,
+            last: 100,
+        };
+        assert!(huge.validate(11, u128::MAX).is_err());
+        assert_eq!(huge.day_volume(5, 11), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_table() {
+        let schedule: EmissionSchedule = serde_json::from_str(
+            r#"{"table":{"steps":[{"from_day":2,"day_volume":10},{"from_day":5,"day_volume":20}]}}"#,
+        )
+        .unwrap();
+        assert_eq!(schedule.day_volume(1, 10), 0);
+        assert_eq!(schedule.day_volume(2, 10), 10);
+        assert_eq!(schedule.day_volume(7, 10), 20);
+        assert_eq!(schedule.total(10).unwrap(), 30 + 100);
+
+        let unsorted = EmissionSchedule::Table {
+            steps: vec![
+                EmissionStep { from_day: 5, day_volume: 1 },
+                EmissionStep { from_day: 2, day_volume: 1 },
+            ],
+        };
+        assert!(unsorted.validate(10, u128::MAX).is_err());
+    }
+}
@@ -1 +1,5 @@
 pub mod staking;
+pub mod proof;
+pub mod config;
+pub mod emission;
+pub mod amount;
\ No newline at end of file
@@ -0,0 +1,433 @@
+
+use alkanes_support::witness::find_witness_payload;
+use anyhow::{anyhow, Result};
+use bitcoin::block::Header;
+use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::opcodes::all::{OP_ENDIF, OP_IF};
+use bitcoin::script::Instruction;
+use bitcoin::{OutPoint, Script, Transaction, TxMerkleNode, Txid, Witness};
+use metashrew_support::utils::{consensus_decode, consume_exact, consume_sized_int};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+/// 难度调整周期，只有该高度的倍数允许修改 bits
+pub const DIFFCHANGE_INTERVAL: u64 = 2016;
+
+/// brc20 金额统一按 18 位小数定点存储
+pub const BRC20_DECIMALS: u32 = 18;
+
+/// Proof that a BRC-20 `transfer` inscription was sent to the pool's deposit script.
+///
+/// `reveal_tx` inscribes the transfer, `transfer_tx` spends its first output to the
+/// deposit script and is tied to the tracked header at `height` by `merkle_branch`.
+/// The proof can't show the inscriber had the balance to back the transfer, that
+/// is attested separately for the inscription.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DepositProof {
+    pub brc20_index: u8,
+    pub period: u16,
+    pub invite_alkanes_id: [u128; 2],
+    pub height: u64,
+    pub tx_index: u32,
+    pub merkle_branch: Vec<[u8; 32]>,
+    pub reveal_tx: Vec<u8>,
+    pub transfer_tx: Vec<u8>,
+}
+
+/// Body of a BRC-20 inscription
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Brc20Inscription {
+    pub p: String,
+    pub op: String,
+    pub tick: String,
+    pub amt: String,
+}
+
+/// A deposit established by a verified proof
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Brc20Deposit {
+    pub tick: String,
+    /// amount scaled by 10^BRC20_DECIMALS
+    pub amount: u128,
+    pub txid: [u8; 32],
+    /// wtxid of the reveal transaction, it commits to the inscription body
+    pub inscription: [u8; 32],
+}
+
+impl DepositProof {
+    pub fn from_tx(raw_tx: Vec<u8>) -> Result<Self> {
+        let tx = consensus_decode::<Transaction>(&mut Cursor::new(raw_tx))?;
+        let data: Vec<u8> = find_witness_payload(&tx, 0).unwrap_or_default();
+        DepositProof::from_vec8(data)
+    }
+
+    pub fn from_vec8(data: Vec<u8>) -> Result<Self> {
+        let mut cursor = Cursor::<Vec<u8>>::new(data);
+        let brc20_index = consume_sized_int::<u8>(&mut cursor)?;
+        let period = consume_sized_int::<u16>(&mut cursor)?;
+        let invite_alkanes_id = [
+            consume_sized_int::<u128>(&mut cursor)?,
+            consume_sized_int::<u128>(&mut cursor)?,
+        ];
+        let height = consume_sized_int::<u64>(&mut cursor)?;
+        let tx_index = consume_sized_int::<u32>(&mut cursor)?;
+        let branch_len = consume_sized_int::<u8>(&mut cursor)?;
+        let mut merkle_branch = Vec::with_capacity(branch_len as usize);
+        for _ in 0..branch_len {
+            merkle_branch.push(consume_exact(&mut cursor, 32)?.try_into().unwrap());
+        }
+        let reveal_len = consume_sized_int::<u32>(&mut cursor)?;
+        let reveal_tx = consume_exact(&mut cursor, reveal_len as usize)?;
+        let transfer_len = consume_sized_int::<u32>(&mut cursor)?;
+        let transfer_tx = consume_exact(&mut cursor, transfer_len as usize)?;
+        Ok(DepositProof {
+            brc20_index,
+            period,
+            invite_alkanes_id,
+            height,
+            tx_index,
+            merkle_branch,
+            reveal_tx,
+            transfer_tx,
+        })
+    }
+
+    pub fn to_vec8(&self) -> Vec<u8> {
+        let mut v = vec![self.brc20_index];
+        v.extend_from_slice(&self.period.to_le_bytes());
+        v.extend_from_slice(&self.invite_alkanes_id[0].to_le_bytes());
+        v.extend_from_slice(&self.invite_alkanes_id[1].to_le_bytes());
+        v.extend_from_slice(&self.height.to_le_bytes());
+        v.extend_from_slice(&self.tx_index.to_le_bytes());
+        v.push(self.merkle_branch.len() as u8);
+        self.merkle_branch.iter().for_each(|h| v.extend_from_slice(h));
+        v.extend_from_slice(&(self.reveal_tx.len() as u32).to_le_bytes());
+        v.extend_from_slice(&self.reveal_tx);
+        v.extend_from_slice(&(self.transfer_tx.len() as u32).to_le_bytes());
+        v.extend_from_slice(&self.transfer_tx);
+        v
+    }
+
+    /// Checks the proof against the tracked `header` at `self.height`
+    pub fn verify(&self, header: &Header, deposit_script: &Script) -> Result<Brc20Deposit> {
+        // 64 字节的交易可以伪装成 merkle 树的内部节点
+        if self.transfer_tx.len() == 64 {
+            return Err(anyhow!("invalid transfer transaction"));
+        }
+        let reveal = consensus_decode::<Transaction>(&mut Cursor::new(self.reveal_tx.clone()))?;
+        let transfer =
+            consensus_decode::<Transaction>(&mut Cursor::new(self.transfer_tx.clone()))?;
+
+        // 铭文位于 reveal 交易 0 号输出的第一个 sat，必须由 transfer 交易的首个输入花费并落到首个输出
+        let inscription_outpoint = OutPoint {
+            txid: reveal.compute_txid(),
+            vout: 0,
+        };
+        if transfer.input.first().map(|i| i.previous_output) != Some(inscription_outpoint) {
+            return Err(anyhow!("transfer does not spend the inscription"));
+        }
+        if transfer.output.first().map(|o| o.script_pubkey.as_script()) != Some(deposit_script) {
+            return Err(anyhow!("inscription not sent to the deposit script"));
+        }
+
+        let txid = transfer.compute_txid();
+        if merkle_root_from_branch(&txid, &self.merkle_branch, self.tx_index)? != header.merkle_root
+        {
+            return Err(anyhow!("invalid merkle proof"));
+        }
+
+        let inscription = parse_brc20_inscription(&reveal)?;
+        if inscription.p != "brc-20" || inscription.op != "transfer" {
+            return Err(anyhow!("not a brc-20 transfer inscription"));
+        }
+        Ok(Brc20Deposit {
+            tick: inscription.tick,
+            amount: parse_brc20_amount(&inscription.amt)?,
+            txid: txid.to_byte_array(),
+            inscription: reveal.compute_wtxid().to_byte_array(),
+        })
+    }
+}
+
+/// Folds `branch` over `txid`, `index` is the position of the transaction in its block
+pub fn merkle_root_from_branch(txid: &Txid, branch: &[[u8; 32]], index: u32) -> Result<TxMerkleNode> {
+    if branch.len() < 32 && (index >> branch.len()) != 0 {
+        return Err(anyhow!("merkle index out of range"));
+    }
+    let mut current = txid.to_byte_array();
+    let mut index = index;
+    for sibling in branch {
+        let mut data = Vec::with_capacity(64);
+        if index & 1 == 1 {
+            data.extend_from_slice(sibling);
+            data.extend_from_slice(&current);
+        } else {
+            data.extend_from_slice(&current);
+            data.extend_from_slice(sibling);
+        }
+        current = sha256d::Hash::hash(&data).to_byte_array();
+        index >>= 1;
+    }
+    Ok(TxMerkleNode::from_byte_array(current))
+}
+
+/// Script of a taproot script-path spend: the element before the control block,
+/// skipping a trailing annex
+fn tapscript(witness: &Witness) -> Option<&Script> {
+    let len = witness.len();
+    let has_annex = len >= 2 && witness.last().is_some_and(|last| last.first() == Some(&0x50));
+    let script_pos = if has_annex { len.checked_sub(3)? } else { len.checked_sub(2)? };
+    witness.nth(script_pos).map(Script::from_bytes)
+}
+
+/// Extracts the BRC-20 body from the ord envelope in the first input of `reveal`
+pub fn parse_brc20_inscription(reveal: &Transaction) -> Result<Brc20Inscription> {
+    let script = reveal
+        .input
+        .first()
+        .and_then(|input| tapscript(&input.witness))
+        .ok_or_else(|| anyhow!("reveal transaction has no tapscript"))?;
+    let instructions = script
+        .instructions()
+        .collect::<Result<Vec<Instruction>, _>>()
+        .map_err(|e| anyhow!("invalid inscription script: {}", e))?;
+
+    let start = instructions
+        .windows(3)
+        .position(|w| {
+            matches!(w[0], Instruction::PushBytes(b) if b.is_empty())
+                && matches!(w[1], Instruction::Op(op) if op == OP_IF)
+                && matches!(w[2], Instruction::PushBytes(b) if b.as_bytes() == b"ord")
+        })
+        .ok_or_else(|| anyhow!("no inscription envelope"))?;
+
+    // 空 push 之前是 tag/value 字段，之后是正文
+    let mut body = Vec::new();
+    let mut in_body = false;
+    for instruction in &instructions[start + 3..] {
+        match instruction {
+            Instruction::Op(op) if *op == OP_ENDIF => break,
+            Instruction::PushBytes(b) if !in_body && b.is_empty() => in_body = true,
+            Instruction::PushBytes(b) if in_body => body.extend_from_slice(b.as_bytes()),
+            _ => {}
+        }
+    }
+    serde_json::from_slice(&body).map_err(|e| anyhow!("invalid brc-20 inscription: {}", e))
+}
+
+/// Parses a BRC-20 `amt` string into a fixed-point value with BRC20_DECIMALS decimals
+pub fn parse_brc20_amount(amt: &str) -> Result<u128> {
+    let (int_part, frac_part) = amt.split_once('.').unwrap_or((amt, ""));
+    let is_digits = |s: &str| s.bytes().all(|c| c.is_ascii_digit());
+    if int_part.is_empty()
+        || !is_digits(int_part)
+        || !is_digits(frac_part)
+        || frac_part.len() > BRC20_DECIMALS as usize
+    {
+        return Err(anyhow!("invalid brc-20 amount {}", amt));
+    }
+    let scale = 10u128.pow(BRC20_DECIMALS);
+    let frac = format!("{:0<width$}", frac_part, width = BRC20_DECIMALS as usize);
+    int_part
+        .parse::<u128>()?
+        .checked_mul(scale)
+        .and_then(|v| v.checked_add(frac.parse::<u128>().ok()?))
+        .ok_or_else(|| anyhow!("brc-20 amount overflow"))
+}
+
+/// Checks that `header` at `height` extends `parent` with valid proof of work.
+///
+/// Bits must match the parent's except on retarget heights, where the target may move
+/// by at most a factor of four like in consensus.
+pub fn validate_next_header(parent: &Header, height: u64, header: &Header) -> Result<()> {
+    if header.prev_blockhash != parent.block_hash() {
+        return Err(anyhow!("header does not extend the tracked chain"));
+    }
+    if height.is_multiple_of(DIFFCHANGE_INTERVAL) {
+        let parent_target = parent.target();
+        let target = header.target();
+        if target < parent_target.min_transition_threshold()
+            || target > parent_target.max_transition_threshold_unchecked()
+        {
+            return Err(anyhow!("invalid difficulty transition"));
+        }
+    } else if header.bits != parent.bits {
+        return Err(anyhow!("unexpected difficulty change"));
+    }
+    header
+        .validate_pow(header.target())
+        .map_err(|e| anyhow!("invalid proof of work: {}", e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::block::Version as BlockVersion;
+    use bitcoin::blockdata::script::Builder;
+    use bitcoin::opcodes::OP_0;
+    use bitcoin::script::PushBytesBuf;
+    use bitcoin::transaction::Version;
+    use bitcoin::{Amount, BlockHash, CompactTarget, ScriptBuf, Sequence, TxIn, TxOut, Witness};
+    use wasm_bindgen_test::*;
+
+    fn reveal_tx(body: &str) -> Transaction {
+        let script = Builder::new()
+            .push_opcode(OP_0)
+            .push_opcode(OP_IF)
+            .push_slice(b"ord")
+            .push_slice([1u8])
+            .push_slice(b"text/plain;charset=utf-8")
+            .push_opcode(OP_0)
+            .push_slice(PushBytesBuf::try_from(body.as_bytes().to_vec()).unwrap())
+            .push_opcode(OP_ENDIF)
+            .into_script();
+        let mut witness = Witness::new();
+        witness.push(script.as_bytes());
+        witness.push([0xc0u8; 33]);
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness,
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(546),
+                script_pubkey: ScriptBuf::new_op_return([1u8; 4]),
+            }],
+        }
+    }
+
+    fn transfer_tx(reveal: &Transaction, script_pubkey: ScriptBuf) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint {
+                    txid: reveal.compute_txid(),
+                    vout: 0,
+                },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(546),
+                script_pubkey,
+            }],
+        }
+    }
+
+    fn mine(mut header: Header) -> Header {
+        while header.validate_pow(header.target()).is_err() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    fn header(prev: BlockHash, merkle_root: TxMerkleNode) -> Header {
+        mine(Header {
+            version: BlockVersion::TWO,
+            prev_blockhash: prev,
+            merkle_root,
+            time: 1700000000,
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce: 0,
+        })
+    }
+
+    #[wasm_bindgen_test]
+    fn test_amount() {
+        assert_eq!(parse_brc20_amount("1").unwrap(), 10u128.pow(18));
+        assert_eq!(parse_brc20_amount("1000.5").unwrap(), 1000500000000000000000);
+        assert_eq!(parse_brc20_amount("0.000000000000000001").unwrap(), 1);
+        assert!(parse_brc20_amount("").is_err());
+        assert!(parse_brc20_amount(".5").is_err());
+        assert!(parse_brc20_amount("1.0000000000000000001").is_err());
+        assert!(parse_brc20_amount("-1").is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_inscription() {
+        let reveal = reveal_tx(r#"{"p":"brc-20","op":"transfer","tick":"sats","amt":"2500"}"#);
+        let inscription = parse_brc20_inscription(&reveal).unwrap();
+        assert_eq!(inscription.tick, "sats");
+        assert_eq!(inscription.amt, "2500");
+        assert!(parse_brc20_inscription(&transfer_tx(&reveal, ScriptBuf::new())).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_merkle_branch() {
+        let txids: Vec<Txid> = (0u8..5)
+            .map(|i| Txid::from_byte_array(sha256d::Hash::hash(&[i]).to_byte_array()))
+            .collect();
+        let root: TxMerkleNode =
+            bitcoin::merkle_tree::calculate_root(txids.iter().map(|t| t.to_raw_hash().into()))
+                .unwrap();
+
+        // 手工构造 index 2 的分支：兄弟节点 3，再到 (0,1)，再到重复的 (4,4)
+        let h = |a: &[u8; 32], b: &[u8; 32]| {
+            let mut d = a.to_vec();
+            d.extend_from_slice(b);
+            sha256d::Hash::hash(&d).to_byte_array()
+        };
+        let t: Vec<[u8; 32]> = txids.iter().map(|t| t.to_byte_array()).collect();
+        let h01 = h(&t[0], &t[1]);
+        let h44 = h(&t[4], &t[4]);
+        let h4444 = h(&h44, &h44);
+        let branch = vec![t[3], h01, h4444];
+        assert_eq!(merkle_root_from_branch(&txids[2], &branch, 2).unwrap(), root);
+        assert_ne!(merkle_root_from_branch(&txids[2], &branch, 3).unwrap(), root);
+        assert!(merkle_root_from_branch(&txids[2], &branch, 8).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_verify() {
+        let deposit_script = ScriptBuf::new_op_return([9u8; 4]);
+        let reveal = reveal_tx(r#"{"p":"brc-20","op":"transfer","tick":"sats","amt":"2500.25"}"#);
+        let transfer = transfer_tx(&reveal, deposit_script.clone());
+        let txid = transfer.compute_txid();
+        let header = header(BlockHash::all_zeros(), TxMerkleNode::from_byte_array(txid.to_byte_array()));
+
+        let proof = DepositProof {
+            brc20_index: 0,
+            period: 30,
+            invite_alkanes_id: [2, 10],
+            height: 900000,
+            tx_index: 0,
+            merkle_branch: vec![],
+            reveal_tx: bitcoin::consensus::serialize(&reveal),
+            transfer_tx: bitcoin::consensus::serialize(&transfer),
+        };
+        assert_eq!(DepositProof::from_vec8(proof.to_vec8()).unwrap(), proof);
+
+        let deposit = proof.verify(&header, &deposit_script).unwrap();
+        assert_eq!(deposit.tick, "sats");
+        assert_eq!(deposit.amount, 2500250000000000000000);
+        assert_eq!(deposit.txid, txid.to_byte_array());
+        assert_eq!(deposit.inscription, reveal.compute_wtxid().to_byte_array());
+
+        assert!(proof.verify(&header, &ScriptBuf::new()).is_err());
+        let other = DepositProof { tx_index: 1, merkle_branch: vec![[1; 32]], ..proof.clone() };
+        assert!(other.verify(&header, &deposit_script).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_header_chain() {
+        let parent = header(BlockHash::all_zeros(), TxMerkleNode::all_zeros());
+        let next = header(parent.block_hash(), TxMerkleNode::all_zeros());
+        assert!(validate_next_header(&parent, 11, &next).is_ok());
+
+        let orphan = header(BlockHash::all_zeros(), TxMerkleNode::all_zeros());
+        assert!(validate_next_header(&parent, 11, &orphan).is_err());
+
+        let mut changed = next;
+        changed.bits = CompactTarget::from_consensus(0x1d00ffff);
+        assert!(validate_next_header(&parent, 11, &changed).is_err());
+    }
+}
@@ -12,6 +12,15 @@ use std::io::Cursor;
 use rust_decimal::Decimal;
 use rust_decimal::prelude::*;
 
+/// 质押池托管的 alkane（复投的 coin 或白名单代币）质押使用的 brc20 index
+pub const ALKANE_STAKING_INDEX: u8 = u8::MAX;
+
+/// `Staking` 当前的编码版本：1 最初格式，2 增加 token_id，3 增加自动续期
+pub const STAKING_VERSION: u8 = 3;
+
+//带版本编码的前缀，旧记录 brc20_index 之后是 bincode 变长整数，不会出现 0xff
+const STAKING_TAG: [u8;2] = [0xff,0xff];
+
 #[derive(Debug,Clone,PartialEq,Default,Serialize,Deserialize)]
 
 //所有区间采用前闭后开
@@ -28,6 +37,80 @@ pub struct Staking {
     // pub expire_height: u64,       //过期区块高度，该高度不算收益  staking_height + period * 144
     pub alkanes_id: [u128;2],
     pub withdraw_coin_value: u128,
+    pub token_id: [u128;2],       //托管的 alkane，brc20 质押为 [0,0]
+    pub auto_renew: bool,         //到期后自动续期同样长度的周期
+    pub renewals: u32,            //关闭自动续期时已经续期的次数
+}
+
+//增加自动续期之前的存储格式
+#[derive(Deserialize)]
+struct StakingV1 {
+    brc20_index: u8,
+    brc20_value: u128,
+    staking_value: u128,
+    period: u16,
+    tx: [u8;32],
+    invite_index: u128,
+    staking_height: u64,
+    unstaking_height: u64,
+    alkanes_id: [u128;2],
+    withdraw_coin_value: u128,
+    token_id: [u128;2],
+}
+
+impl From<StakingV1> for Staking {
+    fn from(v: StakingV1) -> Self {
+        Staking {
+            brc20_index: v.brc20_index,
+            brc20_value: v.brc20_value,
+            staking_value: v.staking_value,
+            period: v.period,
+            tx: v.tx,
+            invite_index: v.invite_index,
+            staking_height: v.staking_height,
+            unstaking_height: v.unstaking_height,
+            alkanes_id: v.alkanes_id,
+            withdraw_coin_value: v.withdraw_coin_value,
+            token_id: v.token_id,
+            auto_renew: false,
+            renewals: 0,
+        }
+    }
+}
+
+//增加 token_id 之前的存储格式
+#[derive(Deserialize)]
+struct StakingV0 {
+    brc20_index: u8,
+    brc20_value: u128,
+    staking_value: u128,
+    period: u16,
+    tx: [u8;32],
+    invite_index: u128,
+    staking_height: u64,
+    unstaking_height: u64,
+    alkanes_id: [u128;2],
+    withdraw_coin_value: u128,
+}
+
+impl From<StakingV0> for Staking {
+    fn from(v: StakingV0) -> Self {
+        Staking {
+            brc20_index: v.brc20_index,
+            brc20_value: v.brc20_value,
+            staking_value: v.staking_value,
+            period: v.period,
+            tx: v.tx,
+            invite_index: v.invite_index,
+            staking_height: v.staking_height,
+            unstaking_height: v.unstaking_height,
+            alkanes_id: v.alkanes_id,
+            withdraw_coin_value: v.withdraw_coin_value,
+            token_id: [0,0],
+            auto_renew: false,
+            renewals: 0,
+        }
+    }
 }
 
 impl Staking {
@@ -51,11 +134,34 @@ impl Staking {
             invite_index: 0,
             unstaking_height: 0,
             withdraw_coin_value: 0,
+            token_id: [0,0],
+            auto_renew: false,
+            renewals: 0,
         })
     }
 
+    /// 自动续期的质押不会到期
     pub fn get_expire_height(&self) -> u64 {
-        self.staking_height + self.period as u64 * 144
+        if self.auto_renew {
+            return u64::MAX;
+        }
+        self.staking_height + self.period as u64 * 144 * (self.renewals as u64 + 1)
+    }
+
+    /// `height` 所在周期的结束高度
+    pub fn get_period_end_height(&self, height: u64) -> u64 {
+        let period_blocks = self.period as u64 * 144;
+        let periods = if period_blocks == 0 { 1 } else { height.saturating_sub(self.staking_height) / period_blocks + 1 };
+        self.staking_height + period_blocks * periods
+    }
+
+    /// 质押的是池中托管的 alkane 而不是链下的 brc20
+    pub fn is_alkane_staking(&self) -> bool {
+        self.brc20_index == ALKANE_STAKING_INDEX
+    }
+
+    pub fn get_token_id(&self) -> AlkaneId {
+        AlkaneId { block: self.token_id[0], tx: self.token_id[1] }
     }
 
     pub fn get_alanes_id(&self) -> AlkaneId {
@@ -77,14 +183,45 @@ impl Staking {
         }
     }
 
+    /// 版本前缀加 bincode 编码
     pub fn serialize(&self) -> Result<Vec<u8>> {
-        encode_to_vec(self, config::standard()).map_err(|e| anyhow!("serialize error:{}", e))
+        let mut v = STAKING_TAG.to_vec();
+        v.push(STAKING_VERSION);
+        v.extend(encode_to_vec(self, config::standard()).map_err(|e| anyhow!("serialize error:{}", e))?);
+        Ok(v)
     }
 
+    /// 记录的编码版本，没有版本前缀的旧记录为 0
+    pub fn encoding_version(v: &Vec<u8>) -> u8 {
+        if v.len() > STAKING_TAG.len() && v[..STAKING_TAG.len()] == STAKING_TAG {
+            return v[STAKING_TAG.len()];
+        }
+        0
+    }
 
+    /// 按版本解码，兼容没有版本前缀的旧记录
     pub fn descrialize(v: &Vec<u8>) -> Result<Self> {
-        let (staking,_) = decode_from_slice(v,config::standard()).map_err(|e|anyhow!("descrialize error:{}", e))?;
-        Ok(staking)
+        let version = Self::encoding_version(v);
+        if version > 0 {
+            let body = &v[STAKING_TAG.len()+1..];
+            let staking = match version {
+                STAKING_VERSION => decode_from_slice::<Staking,_>(body,config::standard()).map(|(s,_)| s),
+                2 => decode_from_slice::<StakingV1,_>(body,config::standard()).map(|(s,_)| s.into()),
+                1 => decode_from_slice::<StakingV0,_>(body,config::standard()).map(|(s,_)| s.into()),
+                _ => return Err(anyhow!("descrialize error: unknown staking version {}",version)),
+            };
+            return staking.map_err(|e|anyhow!("descrialize error:{}", e));
+        }
+
+        //旧记录按字段从多到少尝试
+        if let std::result::Result::Ok((staking,_)) = decode_from_slice(v,config::standard()) {
+            return Ok(staking);
+        }
+        if let std::result::Result::Ok((staking,_)) = decode_from_slice::<StakingV1,_>(v,config::standard()) {
+            return Ok(staking.into());
+        }
+        let (staking,_) = decode_from_slice::<StakingV0,_>(v,config::standard()).map_err(|e|anyhow!("descrialize error:{}", e))?;
+        Ok(staking.into())
     }
 
     pub fn serialize_invite_vec(v: &Vec<u128>) -> Result<Vec<u8>>{
@@ -96,17 +233,166 @@ impl Staking {
         Ok(invite_vec)
     }
 
+    /// 16 字节的 `Decimal::serialize` 定长编码
     pub fn serialize_decimal(d: &Decimal) -> Result<Vec<u8>>{
-        d.to_string().as_bytes().try_into().map_err(|e|anyhow!("serialize error: {}",e))
+        Ok(d.serialize().to_vec())
     }
 
+    /// 读取定长编码，兼容旧版本以字符串保存的数据
+    ///
+    /// 定长编码前两个字节恒为 0，字符串编码不会出现 0 字节，以此区分两种格式
     pub fn descrialize_decimal(v: &Vec<u8>) -> Result<Decimal>{
-        Decimal::from_str(std::str::from_utf8(v).unwrap()).map_err(|e|anyhow!("serialize error: {}",e))
+        if v.len() == 16 && v[0] == 0 && v[1] == 0 {
+            if v[2] > 28 || v[3] & 0x7f != 0 {
+                return Err(anyhow!("descrialize error: invalid decimal flags"));
+            }
+            return Ok(Decimal::deserialize(v[..].try_into().unwrap()));
+        }
+        let s = std::str::from_utf8(v).map_err(|e|anyhow!("descrialize error: {}",e))?;
+        Decimal::from_str(s).map_err(|e|anyhow!("descrialize error: {}",e))
     }
 
 }
 
 
+/// 解质押时生成的 brc20 退还记录，运营方转出 brc20 后登记转账交易
+///
+/// `brc20_value` 为应退还数量，提前解质押扣除的部分记在 `penalty_value`
+#[derive(Debug,Clone,PartialEq,Default,Serialize,Deserialize)]
+pub struct Redemption {
+    pub index: u128,
+    pub brc20_index: u8,
+    pub tick: String,
+    pub brc20_value: u128,
+    pub penalty_value: u128,
+    pub payout_script: Vec<u8>,
+    pub unstaking_height: u64,
+    pub fulfilled_txid: Option<[u8;32]>,
+    pub fulfilled_height: u64,
+}
+
+impl Redemption {
+    pub fn is_fulfilled(&self) -> bool {
+        self.fulfilled_txid.is_some()
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        encode_to_vec(self, config::standard()).map_err(|e| anyhow!("serialize error:{}", e))
+    }
+
+    pub fn descrialize(v: &Vec<u8>) -> Result<Self> {
+        let (redemption,_) = decode_from_slice(v,config::standard()).map_err(|e|anyhow!("descrialize error:{}", e))?;
+        Ok(redemption)
+    }
+}
+
+/// 质押从 `from_day` 起的权重，追加或延长质押时新增一段
+#[derive(Debug,Clone,PartialEq,Default,Serialize,Deserialize)]
+pub struct WeightSegment {
+    pub from_day: u64,
+    #[serde(with = "decimal_bytes")]
+    pub weight: Decimal,
+}
+
+impl WeightSegment {
+    pub fn serialize_vec(v: &Vec<WeightSegment>) -> Result<Vec<u8>> {
+        encode_to_vec(v, config::standard()).map_err(|e| anyhow!("serialize error:{}", e))
+    }
+
+    pub fn descrialize_vec(v: &Vec<u8>) -> Result<Vec<WeightSegment>> {
+        let (segments,_) = decode_from_slice(v,config::standard()).map_err(|e|anyhow!("descrialize error:{}", e))?;
+        Ok(segments)
+    }
+
+    /// `day` 所在段的权重，第一段之前为 0
+    pub fn weight_on(segments: &Vec<WeightSegment>, day: u64) -> Decimal {
+        segments
+            .iter()
+            .take_while(|segment| segment.from_day <= day)
+            .last()
+            .map_or(Decimal::ZERO, |segment| segment.weight)
+    }
+
+    /// 按比例 `f` 拆分每一段权重，返回 (拆出部分, 剩余部分)
+    pub fn split_vec(segments: &Vec<WeightSegment>, f: Decimal) -> (Vec<WeightSegment>, Vec<WeightSegment>) {
+        segments
+            .iter()
+            .map(|segment| {
+                let w = segment.weight * f;
+                (
+                    WeightSegment { from_day: segment.from_day, weight: w },
+                    WeightSegment { from_day: segment.from_day, weight: segment.weight - w },
+                )
+            })
+            .unzip()
+    }
+
+    /// 两组分段按天相加
+    pub fn merge_vec(a: &Vec<WeightSegment>, b: &Vec<WeightSegment>) -> Vec<WeightSegment> {
+        let mut days: Vec<u64> = a.iter().chain(b.iter()).map(|segment| segment.from_day).collect();
+        days.sort();
+        days.dedup();
+        days.into_iter()
+            .map(|day| WeightSegment {
+                from_day: day,
+                weight: Self::weight_on(a, day) + Self::weight_on(b, day),
+            })
+            .collect()
+    }
+}
+
+/// 权重池的收益累加器，`day` 之前的天已经结算
+///
+/// `a` 为每单位权重累计收益 Σ r_d，`b` 为 Σ d·r_d，用于计算线性释放
+#[derive(Debug,Clone,PartialEq,Default,Serialize,Deserialize)]
+pub struct StakingAcc {
+    pub day: u64,
+    #[serde(with = "decimal_bytes")]
+    pub weight: Decimal,
+    #[serde(with = "decimal_bytes")]
+    pub a: Decimal,
+    #[serde(with = "decimal_bytes")]
+    pub b: Decimal,
+}
+
+impl StakingAcc {
+    /// 结算 `day` 这一天，`delta` 为当天生效的权重变化，`volume` 为当天排放量
+    pub fn next(&mut self, delta: Decimal, volume: u128) {
+        self.weight += delta;
+        if self.weight > Decimal::ZERO {
+            let r = Decimal::from(volume) / self.weight;
+            self.a += r;
+            self.b += r * Decimal::from(self.day);
+        }
+        self.day += 1;
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        encode_to_vec(self, config::standard()).map_err(|e| anyhow!("serialize error:{}", e))
+    }
+
+    pub fn descrialize(v: &Vec<u8>) -> Result<Self> {
+        let (acc,_) = decode_from_slice(v,config::standard()).map_err(|e|anyhow!("descrialize error:{}", e))?;
+        Ok(acc)
+    }
+}
+
+/// 以 `Staking::serialize_decimal` 的定长编码序列化 `Decimal` 字段
+pub mod decimal_bytes {
+    use super::Staking;
+    use rust_decimal::Decimal;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(d: &Decimal, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&d.serialize())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Decimal, D::Error> {
+        let v = <Vec<u8>>::deserialize(deserializer)?;
+        Staking::descrialize_decimal(&v).map_err(D::Error::custom)
+    }
+}
+
 #[derive(Debug,Clone,PartialEq,Default,Serialize,Deserialize)]
 pub struct StakingStat {
     #[serde(with = "rust_decimal::serde::str")]
@@ -177,10 +463,44 @@ mod test{
             unstaking_height: 0,
             alkanes_id: [2,12890],
             withdraw_coin_value: 893400,
+            token_id: [2,77],
+            auto_renew: true,
+            renewals: 0,
         };
         let vv = ss.serialize().unwrap();
         // test_print!("{}",hex::encode(&vv.clone()));
         assert_eq!(ss,Staking::descrialize(&vv).unwrap());
+        assert_eq!(Staking::encoding_version(&vv),STAKING_VERSION);
+
+        //没有版本前缀的旧记录不自动续期，更早的记录 token_id 为 [0,0]
+        let raw = &vv[3..];
+        assert_eq!(Staking::encoding_version(&raw.to_vec()),0);
+        assert_eq!(Staking::descrialize(&raw.to_vec()).unwrap(),ss);
+        let legacy = &raw[..raw.len()-2];
+        assert_eq!(Staking::descrialize(&legacy.to_vec()).unwrap(),Staking{auto_renew:false,..ss});
+        let legacy = &raw[..raw.len()-4];
+        assert_eq!(Staking::descrialize(&legacy.to_vec()).unwrap(),Staking{token_id:[0,0],auto_renew:false,..ss});
+
+        //带版本前缀的旧格式
+        let mut tagged = vec![0xff,0xff,2];
+        tagged.extend_from_slice(&raw[..raw.len()-2]);
+        assert_eq!(Staking::descrialize(&tagged).unwrap(),Staking{auto_renew:false,..ss});
+        tagged[2] = 9;
+        assert!(Staking::descrialize(&tagged).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_renewal(){
+        let mut ss = Staking{staking_height: 1000, period: 30, auto_renew: true, ..Default::default()};
+        assert_eq!(ss.get_expire_height(),u64::MAX);
+        assert_eq!(ss.get_period_end_height(999),1000 + 30 * 144);
+        assert_eq!(ss.get_period_end_height(1000 + 30 * 144 - 1),1000 + 30 * 144);
+        assert_eq!(ss.get_period_end_height(1000 + 30 * 144),1000 + 60 * 144);
+
+        ss.auto_renew = false;
+        ss.renewals = 2;
+        assert_eq!(ss.get_expire_height(),1000 + 90 * 144);
+        assert_eq!(ss.get_mining_end_height(u64::MAX),1000 + 90 * 144);
     }
 
     #[wasm_bindgen_test]
@@ -211,6 +531,59 @@ mod test{
         assert_eq!(ss,ss2);
     }
 
+    #[wasm_bindgen_test]
+    fn test_redemption(){
+        let mut r = Redemption{
+            index: 3,
+            brc20_index: 1,
+            tick: "ordi".to_string(),
+            brc20_value: 800000000,
+            payout_script: vec![0x51,0x20,7,7],
+            unstaking_height: 900,
+            ..Default::default()
+        };
+        assert!(!r.is_fulfilled());
+        assert_eq!(Redemption::descrialize(&r.serialize().unwrap()).unwrap(),r);
+        r.fulfilled_txid = Some([9;32]);
+        r.fulfilled_height = 905;
+        assert!(r.is_fulfilled());
+        assert_eq!(Redemption::descrialize(&r.serialize().unwrap()).unwrap(),r);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_weight_segment(){
+        let segments = vec![
+            WeightSegment{from_day: 2, weight: Decimal::from(100)},
+            WeightSegment{from_day: 5, weight: Decimal::from_str("250.5").unwrap()},
+        ];
+        assert_eq!(WeightSegment::descrialize_vec(&WeightSegment::serialize_vec(&segments).unwrap()).unwrap(),segments);
+        assert_eq!(WeightSegment::weight_on(&segments,1),Decimal::ZERO);
+        assert_eq!(WeightSegment::weight_on(&segments,4),Decimal::from(100));
+        assert_eq!(WeightSegment::weight_on(&segments,9),Decimal::from_str("250.5").unwrap());
+
+        let (split,rest) = WeightSegment::split_vec(&segments,Decimal::from_str("0.2").unwrap());
+        assert_eq!(split[0].weight,Decimal::from(20));
+        assert_eq!(rest[1].weight,Decimal::from_str("200.4").unwrap());
+        assert_eq!(WeightSegment::merge_vec(&split,&rest),segments);
+
+        let other = vec![WeightSegment{from_day: 3, weight: Decimal::from(10)}];
+        let merged = WeightSegment::merge_vec(&segments,&other);
+        assert_eq!(merged.iter().map(|s|s.from_day).collect::<Vec<_>>(),vec![2,3,5]);
+        assert_eq!(WeightSegment::weight_on(&merged,2),Decimal::from(100));
+        assert_eq!(WeightSegment::weight_on(&merged,3),Decimal::from(110));
+        assert_eq!(WeightSegment::weight_on(&merged,5),Decimal::from_str("260.5").unwrap());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_staking_acc(){
+        let mut acc = StakingAcc::default();
+        acc.next(Decimal::from(3), 100);
+        acc.next(Decimal::from(-1), 100);
+        let v = acc.serialize().unwrap();
+        assert_eq!(StakingAcc::descrialize(&v).unwrap(),acc);
+        assert!(StakingAcc::descrialize(&v[..v.len()-1].to_vec()).is_err());
+    }
+
     #[wasm_bindgen_test]
     fn test_json(){
         let (p,r,w) = (1u128,10u128,100u128);
@@ -235,6 +608,9 @@ mod test{
             unstaking_height:0,
             alkanes_id: [0, 0],
             withdraw_coin_value:0,
+            token_id: [0, 0],
+            auto_renew: false,
+            renewals: 0,
         });
     }
 
@@ -253,7 +629,20 @@ mod test{
     fn test_deciaml_ser(){
         let d = Decimal::from_str("100000.23444433").unwrap();
         let s = Staking::serialize_decimal(&d).unwrap();
+        assert_eq!(s.len(),16);
         assert_eq!(Staking::descrialize_decimal(&s).unwrap(),d);
+        let neg = Decimal::from_str("-1.5").unwrap();
+        assert_eq!(Staking::descrialize_decimal(&Staking::serialize_decimal(&neg).unwrap()).unwrap(),neg);
+
+        //旧版本的字符串编码
+        assert_eq!(Staking::descrialize_decimal(&b"100000.23444433".to_vec()).unwrap(),d);
+        assert_eq!(Staking::descrialize_decimal(&b"1000000.23444433".to_vec()).unwrap(),Decimal::from_str("1000000.23444433").unwrap());
+
+        assert!(Staking::descrialize_decimal(&vec![0xff,0xfe]).is_err());
+        assert!(Staking::descrialize_decimal(&vec![0;15]).is_err());
+        let mut bad = s.clone();
+        bad[2] = 29;
+        assert!(Staking::descrialize_decimal(&bad).is_err());
 
         // let h = hex::decode("0732303030302e30").unwrap();
         // let dh = Staking::descrialize_decimal(&h).unwrap();
@@ -0,0 +1,350 @@
+use alkanes::indexer::index_block;
+use alkanes::tests::helpers::{self as alkane_helpers, clear};
+use alkanes::view;
+use alkanes_support::{cellpack::Cellpack, envelope::RawEnvelope, gz, id::AlkaneId};
+use anyhow::{anyhow, Result};
+use bitcoin::address::NetworkChecked;
+use bitcoin::{
+    absolute::LockTime, transaction::Version, Address, Amount, Block, OutPoint, ScriptBuf,
+    Sequence, Transaction, TxIn, TxOut, Witness,
+};
+use ordinals::Runestone;
+use protorune::protostone::Protostones;
+use protorune::test_helpers::{create_block_with_coinbase_tx, get_address, ADDRESS1};
+use protorune_support::balance_sheet::{BalanceSheetOperations, ProtoruneRuneId};
+use protorune_support::protostone::{Protostone, ProtostoneEdict};
+use types_support::staking::Staking;
+
+use crate::tests::std::{coin_build, orbital_build, staking_pool_build};
+
+/// Fuel handed to read-only view calls
+pub const VIEW_FUEL: u64 = 100_000_000;
+
+/// Contracts are deployed before the pool's first mining height (450)
+pub const DEPLOY_HEIGHT: u32 = 440;
+
+/// Protocol tag of alkanes inside the protorune runestone
+const ALKANES_PROTOCOL_TAG: u128 = 1;
+
+pub struct Deployment {
+    pub pool: AlkaneId,
+    pub coin: AlkaneId,
+    /// Output holding the single unit of the pool's auth token
+    pub auth_outpoint: OutPoint,
+}
+
+/// Builds a transaction spending `previous_output` with `protostones` in its runestone.
+///
+/// Outputs 0 and 1 both pay to ADDRESS1 so transfer edicts have a second output to
+/// route alkanes to, output 2 is the OP_RETURN. Protostone shadow vouts therefore
+/// start at 4.
+pub fn create_tx(
+    witness: Witness,
+    previous_output: OutPoint,
+    protostones: Vec<Protostone>,
+) -> Transaction {
+    let address: Address<NetworkChecked> = get_address(&ADDRESS1().as_str());
+    let script_pubkey = address.script_pubkey();
+    let runestone: ScriptBuf = (Runestone {
+        etching: None,
+        pointer: None,
+        edicts: Vec::new(),
+        mint: None,
+        protocol: protostones.encipher().ok(),
+    })
+    .encipher();
+
+    Transaction {
+        version: Version::ONE,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness,
+        }],
+        output: vec![
+            TxOut {
+                value: Amount::from_sat(546),
+                script_pubkey: script_pubkey.clone(),
+            },
+            TxOut {
+                value: Amount::from_sat(546),
+                script_pubkey,
+            },
+            TxOut {
+                value: Amount::from_sat(0),
+                script_pubkey: runestone,
+            },
+        ],
+    }
+}
+
+/// Shadow vout of the first protostone of a transaction built by `create_tx`
+pub fn first_protostone_vout(tx: &Transaction) -> u32 {
+    tx.output.len() as u32 + 1
+}
+
+/// Protostone calling `cellpack`, results and refunds go to output 0
+pub fn call(cellpack: Cellpack) -> Protostone {
+    Protostone {
+        message: cellpack.encipher(),
+        pointer: Some(0),
+        refund: Some(0),
+        edicts: vec![],
+        from: None,
+        burn: None,
+        protocol_tag: ALKANES_PROTOCOL_TAG,
+    }
+}
+
+/// Protostone without a message, used to move alkanes between outputs
+pub fn transfer(edicts: Vec<ProtostoneEdict>) -> Protostone {
+    Protostone {
+        message: vec![],
+        pointer: Some(0),
+        refund: Some(0),
+        edicts,
+        from: None,
+        burn: None,
+        protocol_tag: ALKANES_PROTOCOL_TAG,
+    }
+}
+
+pub fn edict(id: &AlkaneId, amount: u128, output: u128) -> ProtostoneEdict {
+    ProtostoneEdict {
+        id: ProtoruneRuneId {
+            block: id.block,
+            tx: id.tx,
+        },
+        amount,
+        output,
+    }
+}
+
+pub fn outpoint(tx: &Transaction, vout: u32) -> OutPoint {
+    OutPoint {
+        txid: tx.compute_txid(),
+        vout,
+    }
+}
+
+/// Witness carrying `payload` in an uncompressed envelope, as read by `find_witness_payload`
+pub fn payload_witness(payload: Vec<u8>) -> Witness {
+    RawEnvelope::from(payload).to_witness(false)
+}
+
+/// Indexes a block at `height` made of a coinbase followed by `txs`
+pub fn index_txs(height: u32, txs: Vec<Transaction>) -> Result<Block> {
+    let mut block = create_block_with_coinbase_tx(height);
+    block.txdata.extend(txs);
+    index_block(&block, height)?;
+    Ok(block)
+}
+
+/// Balance of `id` held by output `vout` of transaction `tx_index` in `block`
+pub fn balance_of(block: &Block, tx_index: usize, vout: u32, id: &AlkaneId) -> Result<u128> {
+    let sheet = alkane_helpers::get_sheet_for_outpoint(block, tx_index, vout)?;
+    Ok(sheet.get_cached(&ProtoruneRuneId {
+        block: id.block,
+        tx: id.tx,
+    }))
+}
+
+pub fn view_call(id: &AlkaneId, inputs: Vec<u128>) -> Result<Vec<u8>> {
+    view::call_view(id, &inputs, VIEW_FUEL)
+}
+
+pub fn view_string(id: &AlkaneId, inputs: Vec<u128>) -> Result<String> {
+    Ok(String::from_utf8(view_call(id, inputs)?)?)
+}
+
+fn parse_alkane_id(s: &str) -> Result<AlkaneId> {
+    let (block, tx) = s
+        .split_once(':')
+        .ok_or_else(|| anyhow!("invalid alkane id {}", s))?;
+    Ok(AlkaneId {
+        block: block.parse()?,
+        tx: tx.parse()?,
+    })
+}
+
+/// Finds the first `[2, n]` alkane whose opcode 99 returns `name`
+pub fn find_alkane(name: &str) -> Result<AlkaneId> {
+    (0..32u128)
+        .map(|tx| AlkaneId { block: 2, tx })
+        .find(|id| view_string(id, vec![99]).map(|v| v == name).unwrap_or(false))
+        .ok_or_else(|| anyhow!("alkane {} not deployed", name))
+}
+
+fn deploy_tx(binary: Vec<u8>, inputs: Vec<u128>) -> Transaction {
+    create_tx(
+        RawEnvelope::from(binary).to_witness(true),
+        OutPoint::null(),
+        vec![call(Cellpack {
+            target: AlkaneId { block: 1, tx: 0 },
+            inputs,
+        })],
+    )
+}
+
+/// Deploys the orbital and coin templates followed by a staking pool initialized with
+/// the default config.
+pub fn deploy_pool() -> Result<Deployment> {
+    deploy_pool_with_config(vec![])
+}
+
+/// Deploys the orbital and coin templates followed by a staking pool initialized with
+/// the JSON `config`.
+///
+/// The pool clones its templates with `[5, n]`, i.e. from `[2, ORBITAL_TEMPLATE_ID]`
+/// and `[2, COIN_TEMPLATE_ID]` (1 and 3). Deploying each template twice puts them on
+/// those ids whether the sequence starts at 0 or right after the genesis alkane.
+/// The pool reads its config from the witness, which holds the binary at deployment,
+/// so it is initialized in its own transaction at `DEPLOY_HEIGHT + 1`. That
+/// transaction spends its own outpoint so the pool's auth token ends up alone.
+pub fn deploy_pool_with_config(config: Vec<u8>) -> Result<Deployment> {
+    clear();
+    index_txs(
+        DEPLOY_HEIGHT,
+        vec![
+            deploy_tx(orbital_build::get_bytes(), vec![0, 0]),
+            deploy_tx(orbital_build::get_bytes(), vec![0, 0]),
+            deploy_tx(coin_build::get_bytes(), vec![0, 0, 0, 0, 0, 0]),
+            deploy_tx(coin_build::get_bytes(), vec![0, 0, 0, 0, 0, 0]),
+            deploy_tx(staking_pool_build::get_bytes(), vec![99]),
+        ],
+    )?;
+
+    let pool = find_alkane("Staking Pool")?;
+    let init_tx = create_tx(
+        payload_witness(config),
+        OutPoint::null(),
+        vec![call(Cellpack {
+            target: pool,
+            inputs: vec![0],
+        })],
+    );
+    index_txs(DEPLOY_HEIGHT + 1, vec![init_tx.clone()])?;
+
+    let coin = parse_alkane_id(&view_string(&pool, vec![1003])?)?;
+    Ok(Deployment {
+        pool,
+        coin,
+        auth_outpoint: outpoint(&init_tx, 0),
+    })
+}
+
+/// Deploys a coin instance named `name` whose whole `cap` lands on output 0 of the
+/// deployment, e.g. to hand out operator tokens
+pub fn deploy_token(height: u32, name: &str, cap: u128) -> Result<(AlkaneId, OutPoint)> {
+    let mut bytes = name.as_bytes().to_vec();
+    bytes.resize(16, 0);
+    let name_part = u128::from_le_bytes(bytes[0..16].try_into()?);
+    let tx = deploy_tx(coin_build::get_bytes(), vec![0, cap, name_part, 0, name_part, 0]);
+    index_txs(height, vec![tx.clone()])?;
+    Ok((find_alkane(name)?, outpoint(&tx, 0)))
+}
+
+/// Deploys a free mint token named `name` (also its symbol) at `height`, the premine
+/// lands on the returned outpoint
+pub fn deploy_free_mint(
+    height: u32,
+    name: &str,
+    premine: u128,
+    value_per_mint: u128,
+    max_mints: u128,
+    start_height: u128,
+    end_height: u128,
+) -> Result<(AlkaneId, OutPoint)> {
+    let mut bytes = name.as_bytes().to_vec();
+    bytes.resize(16, 0);
+    let name_part = u128::from_le_bytes(bytes[0..16].try_into()?);
+    let tx = deploy_tx(
+        coin_build::get_bytes(),
+        vec![1, premine, value_per_mint, max_mints, start_height, end_height, name_part, 0, name_part],
+    );
+    index_txs(height, vec![tx.clone()])?;
+    Ok((find_alkane(name)?, outpoint(&tx, 0)))
+}
+
+/// Deploys an owned token named `name` at `height`, its auth token lands on the
+/// returned outpoint
+pub fn deploy_owned_token(height: u32, name: &str, cap: u128) -> Result<(AlkaneId, OutPoint)> {
+    let mut bytes = name.as_bytes().to_vec();
+    bytes.resize(16, 0);
+    let name_part = u128::from_le_bytes(bytes[0..16].try_into()?);
+    let tx = deploy_tx(coin_build::get_bytes(), vec![0, cap, name_part, 0, name_part, 1]);
+    index_txs(height, vec![tx.clone()])?;
+    Ok((find_alkane(name)?, outpoint(&tx, 0)))
+}
+
+/// Deploys a coin at the reserved id `[4, tx]` and initializes it at `height + 1` with
+/// the gz compressed `metadata` as witness payload, the cap lands on the returned outpoint
+pub fn deploy_token_with_metadata(
+    height: u32,
+    tx: u128,
+    name: &str,
+    cap: u128,
+    metadata: Vec<u8>,
+) -> Result<(AlkaneId, OutPoint)> {
+    let mut bytes = name.as_bytes().to_vec();
+    bytes.resize(16, 0);
+    let name_part = u128::from_le_bytes(bytes[0..16].try_into()?);
+    let token = AlkaneId { block: 4, tx };
+    let deploy = create_tx(
+        RawEnvelope::from(coin_build::get_bytes()).to_witness(true),
+        OutPoint::null(),
+        vec![call(Cellpack {
+            target: AlkaneId { block: 3, tx },
+            inputs: vec![99],
+        })],
+    );
+    index_txs(height, vec![deploy])?;
+    let init = create_tx(
+        payload_witness(gz::compress(metadata)?),
+        OutPoint::null(),
+        vec![call(Cellpack {
+            target: token,
+            inputs: vec![0, cap, name_part, 0, name_part, 0],
+        })],
+    );
+    index_txs(height + 1, vec![init.clone()])?;
+    Ok((token, outpoint(&init, 0)))
+}
+
+/// Witness payload in the layout decoded by `Staking::from_vec8`
+pub fn staking_payload(
+    brc20_index: u8,
+    brc20_value: u128,
+    staking_value: u128,
+    period: u16,
+    tx: [u8; 32],
+    invite: &AlkaneId,
+    staking_height: u64,
+) -> Vec<u8> {
+    let mut payload = vec![brc20_index];
+    payload.extend_from_slice(&brc20_value.to_le_bytes());
+    payload.extend_from_slice(&staking_value.to_le_bytes());
+    payload.extend_from_slice(&period.to_le_bytes());
+    payload.extend_from_slice(&tx);
+    payload.extend_from_slice(&invite.block.to_le_bytes());
+    payload.extend_from_slice(&invite.tx.to_le_bytes());
+    payload.extend_from_slice(&staking_height.to_le_bytes());
+    payload
+}
+
+/// Staking record of orbital `index` as returned by GetAttributes
+pub fn get_staking(pool: &AlkaneId, index: u128) -> Result<Staking> {
+    Ok(serde_json::from_slice(&view_call(pool, vec![1002, index])?)?)
+}
+
+/// (mined, released, withdrawn) of orbital `index` at `height`, the raw amounts returned by
+/// GetProfit
+pub fn get_profit(pool: &AlkaneId, index: u128, height: u128) -> Result<(u128, u128, u128)> {
+    let values: Vec<String> = serde_json::from_slice(&view_call(pool, vec![53, index, height])?)?;
+    if values.len() < 3 {
+        return Err(anyhow!("unexpected profit response {:?}", values));
+    }
+    Ok((values[0].parse()?, values[1].parse()?, values[2].parse()?))
+}
@@ -0,0 +1,4 @@
+pub mod std;
+pub mod helper;
+pub mod test_coin;
+pub mod test_staking_pool;
@@ -0,0 +1,176 @@
+use crate::tests::helper::*;
+use alkanes::tests::helpers::assert_revert_context;
+use alkanes_support::{cellpack::Cellpack, id::AlkaneId};
+use anyhow::Result;
+use bitcoin::{OutPoint, Transaction, Witness};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+fn mint_tx(token: &AlkaneId, previous_output: OutPoint, mints: usize) -> Transaction {
+    create_tx(
+        Witness::new(),
+        previous_output,
+        (0..mints)
+            .map(|_| {
+                call(Cellpack {
+                    target: *token,
+                    inputs: vec![77],
+                })
+            })
+            .collect(),
+    )
+}
+
+fn view_u128(token: &AlkaneId, opcode: u128) -> Result<u128> {
+    Ok(u128::from_le_bytes(view_call(token, vec![opcode])?[0..16].try_into()?))
+}
+
+#[wasm_bindgen_test]
+fn test_free_mint() -> Result<()> {
+    let (token, premine_outpoint) = deploy_free_mint(DEPLOY_HEIGHT, "Fair", 1000, 100, 2, 500, 0)?;
+    assert_eq!(view_u128(&token, 102)?, 1000 + 100 * 2);
+    assert_eq!(view_u128(&token, 101)?, 1000);
+
+    let early = mint_tx(&token, premine_outpoint, 1);
+    index_txs(499, vec![early.clone()])?;
+    assert_revert_context(
+        &OutPoint {
+            txid: early.compute_txid(),
+            vout: first_protostone_vout(&early),
+        },
+        "mint not started",
+    )?;
+
+    // one mint per transaction, the second protostone reverts
+    let twice = mint_tx(&token, outpoint(&early, 0), 2);
+    let block = index_txs(500, vec![twice.clone()])?;
+    assert_eq!(balance_of(&block, 1, 0, &token)?, 1000 + 100);
+    assert_revert_context(
+        &OutPoint {
+            txid: twice.compute_txid(),
+            vout: first_protostone_vout(&twice) + 1,
+        },
+        "already minted in this transaction",
+    )?;
+
+    let last = mint_tx(&token, outpoint(&twice, 0), 1);
+    let over = mint_tx(&token, outpoint(&last, 0), 1);
+    let block = index_txs(501, vec![last, over.clone()])?;
+    assert_eq!(balance_of(&block, 2, 0, &token)?, 1000 + 200);
+    assert_revert_context(
+        &OutPoint {
+            txid: over.compute_txid(),
+            vout: first_protostone_vout(&over),
+        },
+        "all mints claimed",
+    )?;
+    assert_eq!(view_u128(&token, 103)?, 2);
+    assert_eq!(view_u128(&token, 101)?, 1200);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_burn() -> Result<()> {
+    let (token, outpoint_0) = deploy_token(DEPLOY_HEIGHT, "Burnable", 1000)?;
+    let split = create_tx(
+        Witness::new(),
+        outpoint_0,
+        vec![transfer(vec![edict(&token, 400, 1)])],
+    );
+    let burn = create_tx(
+        Witness::new(),
+        outpoint(&split, 0),
+        vec![call(Cellpack {
+            target: token,
+            inputs: vec![88],
+        })],
+    );
+    let block = index_txs(DEPLOY_HEIGHT + 1, vec![split, burn])?;
+    assert_eq!(balance_of(&block, 1, 1, &token)?, 400);
+    assert_eq!(balance_of(&block, 2, 0, &token)?, 0);
+    assert_eq!(view_u128(&token, 101)?, 400);
+    assert_eq!(view_u128(&token, 106)?, 600);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_owned_mint() -> Result<()> {
+    let (token, outpoint_0) = deploy_owned_token(DEPLOY_HEIGHT, "Owned", 1000)?;
+    // nothing is minted up front
+    assert_eq!(view_u128(&token, 101)?, 0);
+
+    let mint = create_tx(
+        Witness::new(),
+        outpoint_0,
+        vec![call(Cellpack {
+            target: token,
+            inputs: vec![78, 700],
+        })],
+    );
+    let unauthorized = create_tx(
+        Witness::new(),
+        OutPoint::null(),
+        vec![call(Cellpack {
+            target: token,
+            inputs: vec![78, 1],
+        })],
+    );
+    let block = index_txs(DEPLOY_HEIGHT + 1, vec![mint.clone(), unauthorized.clone()])?;
+    assert_eq!(balance_of(&block, 1, 0, &token)?, 700);
+    assert_revert_context(
+        &OutPoint {
+            txid: unauthorized.compute_txid(),
+            vout: first_protostone_vout(&unauthorized),
+        },
+        "did not authenticate with only the auth token",
+    )?;
+
+    let split = create_tx(
+        Witness::new(),
+        outpoint(&mint, 0),
+        vec![transfer(vec![edict(&token, 700, 1)])],
+    );
+    let over = create_tx(
+        Witness::new(),
+        outpoint(&split, 0),
+        vec![call(Cellpack {
+            target: token,
+            inputs: vec![78, 301],
+        })],
+    );
+    index_txs(DEPLOY_HEIGHT + 2, vec![split, over.clone()])?;
+    assert_revert_context(
+        &OutPoint {
+            txid: over.compute_txid(),
+            vout: first_protostone_vout(&over),
+        },
+        "supply cap reached",
+    )?;
+    assert_eq!(view_u128(&token, 101)?, 700);
+    assert_eq!(view_u128(&token, 102)?, 1000);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_metadata() -> Result<()> {
+    let metadata = br#"{"description":"Forge coin","url":"https://example.com","decimals":6}"#;
+    let (token, _) = deploy_token_with_metadata(DEPLOY_HEIGHT, 100, "Meta", 1000, metadata.to_vec())?;
+    assert_eq!(view_string(&token, vec![99])?, "Meta");
+    assert_eq!(view_call(&token, vec![1000])?, metadata.to_vec());
+    assert_eq!(view_string(&token, vec![1001])?, "application/json");
+    assert_eq!(view_string(&token, vec![1002])?, "Forge coin");
+    assert_eq!(view_string(&token, vec![1003])?, "https://example.com");
+    assert_eq!(view_call(&token, vec![1004])?, 6u128.to_le_bytes().to_vec());
+
+    let image = b"\x89PNG\r\n\x1a\n".to_vec();
+    let (token, _) = deploy_token_with_metadata(DEPLOY_HEIGHT + 2, 101, "Image", 1000, image.clone())?;
+    assert_eq!(view_call(&token, vec![1000])?, image);
+    assert_eq!(view_string(&token, vec![1001])?, "image/png");
+    assert_eq!(view_call(&token, vec![1004])?, 8u128.to_le_bytes().to_vec());
+
+    // the bytecode of a deployment is not metadata
+    let (token, _) = deploy_token(DEPLOY_HEIGHT + 4, "Plain", 1000)?;
+    assert!(view_call(&token, vec![1000])?.is_empty());
+    assert!(view_call(&token, vec![1001])?.is_empty());
+    assert_eq!(view_call(&token, vec![1004])?, 8u128.to_le_bytes().to_vec());
+    Ok(())
+}
@@ -0,0 +1,1397 @@
+use crate::tests::helper::*;
+use alkanes::tests::helpers::assert_revert_context;
+use alkanes_support::{cellpack::Cellpack, id::AlkaneId};
+use anyhow::Result;
+use bitcoin::block::{Header, Version as BlockVersion};
+use bitcoin::blockdata::script::Builder;
+use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::opcodes::all::{OP_ENDIF, OP_IF};
+use bitcoin::opcodes::OP_0;
+use bitcoin::script::PushBytesBuf;
+use bitcoin::{
+    absolute::LockTime, transaction::Version, Amount, BlockHash, CompactTarget, OutPoint,
+    ScriptBuf, Sequence, TxIn, TxMerkleNode, TxOut, Witness,
+};
+use types_support::proof::DepositProof;
+use types_support::amount::format_amount;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const STAKING_HEIGHT: u64 = 455;
+const STAKING_BLOCK: u32 = 460;
+const COIN_CAP: u128 = 100000000000000000;
+
+fn stake_tx(
+    pool: &AlkaneId,
+    previous_output: OutPoint,
+    period: u16,
+    staking_value: u128,
+) -> bitcoin::Transaction {
+    invited_stake_tx(pool, previous_output, period, staking_value, &AlkaneId { block: 0, tx: 0 })
+}
+
+fn invited_stake_tx(
+    pool: &AlkaneId,
+    previous_output: OutPoint,
+    period: u16,
+    staking_value: u128,
+    invite: &AlkaneId,
+) -> bitcoin::Transaction {
+    // each deposit txid can only be staked once
+    let mut deposit_txid = [7; 32];
+    deposit_txid[..16].copy_from_slice(&staking_value.to_le_bytes());
+    let payload = staking_payload(
+        0,
+        800000000,
+        staking_value,
+        period,
+        deposit_txid,
+        invite,
+        STAKING_HEIGHT,
+    );
+    create_tx(
+        payload_witness(payload),
+        previous_output,
+        vec![call(Cellpack {
+            target: *pool,
+            inputs: vec![50],
+        })],
+    )
+}
+
+fn admin_tx(pool: &AlkaneId, previous_output: OutPoint, inputs: Vec<u128>) -> bitcoin::Transaction {
+    create_tx(
+        Witness::new(),
+        previous_output,
+        vec![call(Cellpack {
+            target: *pool,
+            inputs,
+        })],
+    )
+}
+
+#[wasm_bindgen_test]
+fn test_initialize() -> Result<()> {
+    let deployment = deploy_pool()?;
+
+    assert_eq!(view_string(&deployment.pool, vec![99])?, "Staking Pool");
+    assert_eq!(view_string(&deployment.pool, vec![100])?, "SP");
+    assert_eq!(view_string(&deployment.coin, vec![99])?, "Alkanes Forge");
+    assert_eq!(view_string(&deployment.coin, vec![100])?, "forge");
+
+    // the pool holds the coin's auth token and mints rewards when they are paid
+    let supply = view_call(&deployment.coin, vec![101])?;
+    assert_eq!(u128::from_le_bytes(supply[0..16].try_into()?), 0);
+    let cap = view_call(&deployment.coin, vec![102])?;
+    assert_eq!(u128::from_le_bytes(cap[0..16].try_into()?), COIN_CAP);
+    assert_eq!(view_string(&deployment.pool, vec![1004])?, "0");
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_initialize_with_config() -> Result<()> {
+    let deployment = deploy_pool_with_config(
+        br#"{"cap":1000000000,"mining_cap":360000000,"mining_one_day_volume":1000000,"min_staking_value":100000,"coin_name":"Test Coin","coin_symbol":"tc"}"#
+            .to_vec(),
+    )?;
+
+    let config: serde_json::Value =
+        serde_json::from_slice(&view_call(&deployment.pool, vec![1007])?)?;
+    assert_eq!(config["cap"], 1000000000);
+    assert_eq!(config["min_staking_value"], 100000);
+    // unspecified fields keep their defaults
+    assert_eq!(config["mining_first_height"], 450);
+
+    assert_eq!(view_string(&deployment.coin, vec![99])?, "Test Coin");
+    assert_eq!(view_string(&deployment.coin, vec![100])?, "tc");
+    let cap = view_call(&deployment.coin, vec![102])?;
+    assert_eq!(u128::from_le_bytes(cap[0..16].try_into()?), 1000000000);
+    assert_eq!(view_string(&deployment.pool, vec![1004])?, "0");
+
+    let stake = stake_tx(&deployment.pool, deployment.auth_outpoint, 30, 50000);
+    index_txs(STAKING_BLOCK, vec![stake.clone()])?;
+    assert_revert_context(
+        &OutPoint {
+            txid: stake.compute_txid(),
+            vout: first_protostone_vout(&stake),
+        },
+        "Not enough value",
+    )?;
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_staking_requires_auth_token() -> Result<()> {
+    let deployment = deploy_pool()?;
+    let mut tx = stake_tx(&deployment.pool, deployment.auth_outpoint, 30, 50000);
+    tx.input[0].previous_output = OutPoint::null();
+    index_txs(STAKING_BLOCK, vec![tx.clone()])?;
+
+    assert_revert_context(
+        &OutPoint {
+            txid: tx.compute_txid(),
+            vout: first_protostone_vout(&tx),
+        },
+        "did not authenticate with only the auth token",
+    )?;
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_staking_claim_unstaking() -> Result<()> {
+    let deployment = deploy_pool()?;
+
+    // register a stake, the orbital comes back next to the auth token
+    let stake = stake_tx(&deployment.pool, deployment.auth_outpoint, 30, 50000);
+    let block = index_txs(STAKING_BLOCK, vec![stake.clone()])?;
+    let staking = get_staking(&deployment.pool, 1)?;
+    assert_eq!(staking.staking_height, STAKING_HEIGHT);
+    assert_eq!(staking.staking_value, 50000);
+    assert_eq!(staking.period, 30);
+    let orbital = staking.get_alanes_id();
+    assert_eq!(balance_of(&block, 1, 0, &orbital)?, 1);
+    assert_eq!(balance_of(&block, 1, 0, &deployment.pool)?, 1);
+
+    // move the orbital to its own output so it can authenticate alone
+    let split = create_tx(
+        Witness::new(),
+        outpoint(&stake, 0),
+        vec![transfer(vec![edict(&orbital, 1, 1)])],
+    );
+    let block = index_txs(STAKING_BLOCK + 1, vec![split.clone()])?;
+    assert_eq!(balance_of(&block, 1, 1, &orbital)?, 1);
+    assert_eq!(balance_of(&block, 1, 0, &orbital)?, 0);
+
+    // claim released rewards through the orbital
+    let claim_height = (STAKING_HEIGHT + 144 * 3) as u32;
+    let (mined, released, withdrawn) =
+        get_profit(&deployment.pool, 1, claim_height as u128)?;
+    assert!(mined > 0);
+    assert!(released > 0 && released < mined);
+    assert_eq!(withdrawn, 0);
+
+    let claim = create_tx(
+        Witness::new(),
+        outpoint(&split, 1),
+        vec![call(Cellpack {
+            target: orbital,
+            inputs: vec![1005],
+        })],
+    );
+    let block = index_txs(claim_height, vec![claim.clone()])?;
+    assert_eq!(balance_of(&block, 1, 0, &deployment.coin)?, released);
+    assert_eq!(balance_of(&block, 1, 0, &orbital)?, 1);
+    assert_eq!(get_profit(&deployment.pool, 1, claim_height as u128)?.2, released);
+    // the claimed rewards are minted when they are paid
+    let supply = view_call(&deployment.coin, vec![101])?;
+    assert_eq!(u128::from_le_bytes(supply[0..16].try_into()?), released);
+
+    // separate the claimed coins from the orbital, then unstake
+    let unstake_height = claim_height + 144;
+    let split = create_tx(
+        Witness::new(),
+        outpoint(&claim, 0),
+        vec![transfer(vec![edict(&deployment.coin, released, 1)])],
+    );
+    let unstake = create_tx(
+        Witness::new(),
+        outpoint(&split, 0),
+        vec![call(Cellpack {
+            target: orbital,
+            inputs: vec![1004],
+        })],
+    );
+    index_txs(unstake_height, vec![split, unstake])?;
+    assert_eq!(
+        get_staking(&deployment.pool, 1)?.unstaking_height,
+        unstake_height as u64
+    );
+
+    // nothing is mined after unstaking
+    let (mined_at_exit, _, _) = get_profit(&deployment.pool, 1, unstake_height as u128)?;
+    let (mined_later, _, _) =
+        get_profit(&deployment.pool, 1, (unstake_height + 144 * 5) as u128)?;
+    assert_eq!(mined_at_exit, mined_later);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_staking_operator_threshold() -> Result<()> {
+    let deployment = deploy_pool()?;
+    let (operator_a, outpoint_a) = deploy_token(DEPLOY_HEIGHT + 2, "Operator A", 1)?;
+    let (operator_b, outpoint_b) = deploy_token(DEPLOY_HEIGHT + 3, "Operator B", 1)?;
+
+    let add_a = admin_tx(
+        &deployment.pool,
+        deployment.auth_outpoint,
+        vec![73, operator_a.block, operator_a.tx],
+    );
+    let add_b = admin_tx(
+        &deployment.pool,
+        outpoint(&add_a, 0),
+        vec![73, operator_b.block, operator_b.tx],
+    );
+    let threshold = admin_tx(&deployment.pool, outpoint(&add_b, 0), vec![75, 2]);
+    index_txs(DEPLOY_HEIGHT + 4, vec![add_a, add_b, threshold])?;
+
+    let operators: serde_json::Value =
+        serde_json::from_slice(&view_call(&deployment.pool, vec![1006])?)?;
+    assert_eq!(operators["threshold"], "2");
+    assert_eq!(operators["operators"].as_array().map(|v| v.len()), Some(2));
+
+    // the first approval is only recorded, the operator token is handed back
+    let approve_a = stake_tx(&deployment.pool, outpoint_a, 30, 50000);
+    let block = index_txs(STAKING_BLOCK, vec![approve_a.clone()])?;
+    assert!(get_staking(&deployment.pool, 1).is_err());
+    assert_eq!(balance_of(&block, 1, 0, &operator_a)?, 1);
+
+    // the second one registers the staking
+    let approve_b = stake_tx(&deployment.pool, outpoint_b, 30, 50000);
+    let block = index_txs(STAKING_BLOCK + 1, vec![approve_b.clone()])?;
+    let orbital = get_staking(&deployment.pool, 1)?.get_alanes_id();
+    assert_eq!(balance_of(&block, 1, 0, &orbital)?, 1);
+    assert_eq!(balance_of(&block, 1, 0, &operator_b)?, 1);
+
+    // the same payload cannot be registered twice
+    let replay = stake_tx(&deployment.pool, outpoint(&approve_a, 0), 30, 50000);
+    index_txs(STAKING_BLOCK + 2, vec![replay.clone()])?;
+    assert_revert_context(
+        &OutPoint {
+            txid: replay.compute_txid(),
+            vout: first_protostone_vout(&replay),
+        },
+        "staking already registered",
+    )?;
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_removed_operator_approval() -> Result<()> {
+    let deployment = deploy_pool()?;
+    let (operator_a, outpoint_a) = deploy_token(DEPLOY_HEIGHT + 2, "Operator A", 1)?;
+    let (operator_b, outpoint_b) = deploy_token(DEPLOY_HEIGHT + 3, "Operator B", 1)?;
+    let (operator_c, outpoint_c) = deploy_token(DEPLOY_HEIGHT + 4, "Operator C", 1)?;
+
+    let add_a = admin_tx(
+        &deployment.pool,
+        deployment.auth_outpoint,
+        vec![73, operator_a.block, operator_a.tx],
+    );
+    let add_b = admin_tx(
+        &deployment.pool,
+        outpoint(&add_a, 0),
+        vec![73, operator_b.block, operator_b.tx],
+    );
+    let add_c = admin_tx(
+        &deployment.pool,
+        outpoint(&add_b, 0),
+        vec![73, operator_c.block, operator_c.tx],
+    );
+    let threshold = admin_tx(&deployment.pool, outpoint(&add_c, 0), vec![75, 2]);
+    index_txs(DEPLOY_HEIGHT + 5, vec![add_a, add_b, add_c, threshold.clone()])?;
+
+    // the approval of a removed operator no longer counts
+    let approve_a = stake_tx(&deployment.pool, outpoint_a, 30, 50000);
+    let remove_a = admin_tx(
+        &deployment.pool,
+        outpoint(&threshold, 0),
+        vec![74, operator_a.block, operator_a.tx],
+    );
+    let approve_b = stake_tx(&deployment.pool, outpoint_b, 30, 50000);
+    index_txs(STAKING_BLOCK, vec![approve_a, remove_a, approve_b])?;
+    assert!(get_staking(&deployment.pool, 1).is_err());
+
+    let approve_c = stake_tx(&deployment.pool, outpoint_c, 30, 50000);
+    let block = index_txs(STAKING_BLOCK + 1, vec![approve_c])?;
+    let orbital = get_staking(&deployment.pool, 1)?.get_alanes_id();
+    assert_eq!(balance_of(&block, 1, 0, &orbital)?, 1);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_period_weight_table() -> Result<()> {
+    let deployment = deploy_pool()?;
+
+    // periods outside the table are rejected
+    let stake = stake_tx(&deployment.pool, deployment.auth_outpoint, 60, 50000);
+    index_txs(STAKING_BLOCK, vec![stake.clone()])?;
+    assert_revert_context(
+        &OutPoint {
+            txid: stake.compute_txid(),
+            vout: first_protostone_vout(&stake),
+        },
+        "unsupported staking period",
+    )?;
+
+    // the refunded auth token replaces the table
+    let set_weights = create_tx(
+        payload_witness(br#"[{"period":60,"weight":"1.2"}]"#.to_vec()),
+        outpoint(&stake, 0),
+        vec![call(Cellpack {
+            target: deployment.pool,
+            inputs: vec![76],
+        })],
+    );
+    let stake = stake_tx(&deployment.pool, outpoint(&set_weights, 0), 60, 50000);
+    index_txs(STAKING_BLOCK + 1, vec![set_weights, stake])?;
+
+    let weights: serde_json::Value =
+        serde_json::from_slice(&view_call(&deployment.pool, vec![1008])?)?;
+    assert_eq!(weights[0]["period"], 60);
+    assert_eq!(weights[0]["weight"], "1.2");
+    assert_eq!(get_staking(&deployment.pool, 1)?.period, 60);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_referral_rewards() -> Result<()> {
+    let deployment = deploy_pool_with_config(
+        br#"{"referral_rates":["0.1"],"mining_cap":60000000000000000}"#.to_vec(),
+    )?;
+
+    let inviter = stake_tx(&deployment.pool, deployment.auth_outpoint, 30, 50000);
+    index_txs(STAKING_BLOCK, vec![inviter.clone()])?;
+    let inviter_orbital = get_staking(&deployment.pool, 1)?.get_alanes_id();
+
+    // the auth token and the inviter's orbital part ways before the invitee stakes
+    let split = create_tx(
+        Witness::new(),
+        outpoint(&inviter, 0),
+        vec![transfer(vec![edict(&inviter_orbital, 1, 1)])],
+    );
+    let invitee = invited_stake_tx(
+        &deployment.pool,
+        outpoint(&split, 0),
+        30,
+        100000,
+        &inviter_orbital,
+    );
+    index_txs(STAKING_BLOCK + 1, vec![split.clone(), invitee.clone()])?;
+    assert_eq!(get_staking(&deployment.pool, 2)?.invite_index, 1);
+    let invitee_orbital = get_staking(&deployment.pool, 2)?.get_alanes_id();
+
+    let invitees: Vec<String> = serde_json::from_slice(&view_call(&deployment.pool, vec![1009, 1])?)?;
+    assert_eq!(invitees, vec!["2"]);
+
+    // nothing is earned until the invitee claims
+    let claim_height = (STAKING_HEIGHT + 144 * 3) as u32;
+    let (_, released, _) = get_profit(&deployment.pool, 2, claim_height as u128)?;
+    let earnings = |height: u32| -> Result<Vec<String>> {
+        Ok(serde_json::from_slice(&view_call(
+            &deployment.pool,
+            vec![1010, 1, height as u128],
+        )?)?)
+    };
+    assert_eq!(earnings(claim_height)?, vec!["0".to_string(), "0".to_string()]);
+
+    let split_invitee = create_tx(
+        Witness::new(),
+        outpoint(&invitee, 0),
+        vec![transfer(vec![edict(&invitee_orbital, 1, 1)])],
+    );
+    let invitee_claim = create_tx(
+        Witness::new(),
+        outpoint(&split_invitee, 1),
+        vec![call(Cellpack {
+            target: invitee_orbital,
+            inputs: vec![1005],
+        })],
+    );
+    index_txs(claim_height, vec![split_invitee, invitee_claim])?;
+    assert_eq!(earnings(claim_height)?, vec![(released / 10).to_string(), "0".to_string()]);
+
+    let claim = create_tx(
+        Witness::new(),
+        outpoint(&split, 1),
+        vec![call(Cellpack {
+            target: inviter_orbital,
+            inputs: vec![1006],
+        })],
+    );
+    let block = index_txs(claim_height + 1, vec![claim])?;
+    assert_eq!(balance_of(&block, 1, 0, &deployment.coin)?, released / 10);
+    assert_eq!(balance_of(&block, 1, 0, &inviter_orbital)?, 1);
+
+    let stats: serde_json::Value = serde_json::from_slice(&view_call(&deployment.pool, vec![1018])?)?;
+    assert_eq!(stats["referral_credited"], (released / 10).to_string());
+    assert_eq!(stats["referral_claimed"], (released / 10).to_string());
+
+    // the inviter's own mining rewards are untouched
+    assert_eq!(get_profit(&deployment.pool, 1, claim_height as u128)?.2, 0);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_add_brc20_ticker() -> Result<()> {
+    let deployment = deploy_pool()?;
+
+    let add_ticker = |previous_output, ticker: &[u8]| {
+        create_tx(
+            payload_witness(ticker.to_vec()),
+            previous_output,
+            vec![call(Cellpack {
+                target: deployment.pool,
+                inputs: vec![77],
+            })],
+        )
+    };
+    let ordi = add_ticker(
+        deployment.auth_outpoint,
+        br#"{"name":"ordi","ratio":"2.5","decimals":8}"#,
+    );
+    // a second budget that would overdraw the coin cap is refused
+    let greedy = add_ticker(
+        outpoint(&ordi, 0),
+        br#"{"name":"pepe","day_volume":1000000000000000}"#,
+    );
+    index_txs(STAKING_BLOCK, vec![ordi, greedy.clone()])?;
+    assert_revert_context(
+        &OutPoint {
+            txid: greedy.compute_txid(),
+            vout: first_protostone_vout(&greedy),
+        },
+        "brc20 emission exceeds cap",
+    )?;
+
+    let tickers: serde_json::Value =
+        serde_json::from_slice(&view_call(&deployment.pool, vec![1011])?)?;
+    assert_eq!(tickers.as_array().map(|v| v.len()), Some(2));
+    assert_eq!(tickers[0]["name"], "sats");
+    assert_eq!(tickers[1]["name"], "ordi");
+    assert_eq!(tickers[1]["ratio"], "2.5");
+    assert_eq!(tickers[0]["decimals"], 18);
+    assert_eq!(tickers[1]["decimals"], 8);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_emission_schedule() -> Result<()> {
+    let deployment = deploy_pool_with_config(
+        br#"{"emission":{"table":{"steps":[{"from_day":0,"day_volume":3000000},{"from_day":1,"day_volume":1000000}]}}}"#
+            .to_vec(),
+    )?;
+    let stake = stake_tx(&deployment.pool, deployment.auth_outpoint, 30, 50000);
+    index_txs(STAKING_BLOCK, vec![stake])?;
+
+    // a lone staker mines each day's volume
+    let (mined, _, _) = get_profit(&deployment.pool, 1, (STAKING_HEIGHT + 144) as u128)?;
+    assert_eq!(mined, 3000000);
+    let (mined, _, _) = get_profit(&deployment.pool, 1, (STAKING_HEIGHT + 144 * 3) as u128)?;
+    assert_eq!(mined, 5000000);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_unstaking_redemption() -> Result<()> {
+    let deployment = deploy_pool()?;
+    let stake = stake_tx(&deployment.pool, deployment.auth_outpoint, 30, 50000);
+    index_txs(STAKING_BLOCK, vec![stake.clone()])?;
+    let orbital = get_staking(&deployment.pool, 1)?.get_alanes_id();
+
+    let split = create_tx(
+        Witness::new(),
+        outpoint(&stake, 0),
+        vec![transfer(vec![edict(&orbital, 1, 1)])],
+    );
+    // the BRC-20 is paid back to the script carried in the unstaking witness
+    let payout_script = vec![0x51, 0x20, 0xaa, 0xbb];
+    let unstake = create_tx(
+        payload_witness(payout_script.clone()),
+        outpoint(&split, 1),
+        vec![call(Cellpack {
+            target: orbital,
+            inputs: vec![1004],
+        })],
+    );
+    let unstake_height = STAKING_BLOCK + 144;
+    index_txs(unstake_height, vec![split.clone(), unstake])?;
+
+    let redemption: serde_json::Value =
+        serde_json::from_slice(&view_call(&deployment.pool, vec![1012, 1])?)?;
+    assert_eq!(redemption["tick"], "sats");
+    assert_eq!(redemption["brc20_value"], "800000000");
+    assert_eq!(redemption["payout_script"], hex::encode(&payout_script));
+    assert_eq!(redemption["unstaking_height"], unstake_height as u64);
+    assert!(redemption["fulfilled_txid"].is_null());
+    let pending: Vec<String> = serde_json::from_slice(&view_call(&deployment.pool, vec![1013])?)?;
+    assert_eq!(pending, vec!["1"]);
+
+    // without operators the auth token records the BRC-20 transfer
+    let fulfill = |previous_output, txid: [u8; 32]| {
+        create_tx(
+            payload_witness(txid.to_vec()),
+            previous_output,
+            vec![call(Cellpack {
+                target: deployment.pool,
+                inputs: vec![78, 1],
+            })],
+        )
+    };
+    let first = fulfill(outpoint(&split, 0), [5; 32]);
+    let again = fulfill(outpoint(&first, 0), [6; 32]);
+    index_txs(unstake_height + 1, vec![first, again.clone()])?;
+    assert_revert_context(
+        &OutPoint {
+            txid: again.compute_txid(),
+            vout: first_protostone_vout(&again),
+        },
+        "redemption already fulfilled",
+    )?;
+
+    let redemption: serde_json::Value =
+        serde_json::from_slice(&view_call(&deployment.pool, vec![1012, 1])?)?;
+    assert_eq!(redemption["fulfilled_txid"], hex::encode([5; 32]));
+    assert_eq!(redemption["fulfilled_height"], (unstake_height + 1) as u64);
+    let pending: Vec<String> = serde_json::from_slice(&view_call(&deployment.pool, vec![1013])?)?;
+    assert!(pending.is_empty());
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_early_unstake_penalty() -> Result<()> {
+    let deployment = deploy_pool_with_config(
+        br#"{"early_unstake_penalty":{"reward_rate":"0.5","principal_rate":"0.1","destination":"burn"}}"#
+            .to_vec(),
+    )?;
+    let stake = stake_tx(&deployment.pool, deployment.auth_outpoint, 30, 50000);
+    index_txs(STAKING_BLOCK, vec![stake.clone()])?;
+    let orbital = get_staking(&deployment.pool, 1)?.get_alanes_id();
+
+    let unstake_height = STAKING_BLOCK + 144 * 3;
+    let (mined, released, _) = get_profit(&deployment.pool, 1, unstake_height as u128)?;
+    let forfeited = (mined - released) / 2;
+
+    let split = create_tx(
+        Witness::new(),
+        outpoint(&stake, 0),
+        vec![transfer(vec![edict(&orbital, 1, 1)])],
+    );
+    let unstake = create_tx(
+        Witness::new(),
+        outpoint(&split, 1),
+        vec![call(Cellpack {
+            target: orbital,
+            inputs: vec![1004],
+        })],
+    );
+    index_txs(unstake_height, vec![split, unstake])?;
+
+    // half of the unreleased rewards is burned, the other half released at once
+    assert_eq!(
+        get_profit(&deployment.pool, 1, unstake_height as u128)?,
+        (mined, mined - forfeited, 0)
+    );
+    let stats: serde_json::Value =
+        serde_json::from_slice(&view_call(&deployment.pool, vec![1014])?)?;
+    assert_eq!(stats["burned"], forfeited.to_string());
+    assert_eq!(stats["treasury"], "0");
+    // the burned coins leave the coin's total supply, nothing else was claimed
+    let supply = view_call(&deployment.coin, vec![101])?;
+    assert_eq!(u128::from_le_bytes(supply[0..16].try_into()?), 0);
+    let burned = view_call(&deployment.coin, vec![106])?;
+    assert_eq!(u128::from_le_bytes(burned[0..16].try_into()?), forfeited);
+
+    let redemption: serde_json::Value =
+        serde_json::from_slice(&view_call(&deployment.pool, vec![1012, 1])?)?;
+    assert_eq!(redemption["brc20_value"], "720000000");
+    assert_eq!(redemption["penalty_value"], "80000000");
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_auto_renew_unstake_at_period_end() -> Result<()> {
+    let deployment = deploy_pool_with_config(
+        br#"{"early_unstake_penalty":{"reward_rate":"0.5","principal_rate":"0.1","destination":"treasury"}}"#
+            .to_vec(),
+    )?;
+    let stake = stake_tx(&deployment.pool, deployment.auth_outpoint, 30, 50000);
+    index_txs(STAKING_BLOCK, vec![stake.clone()])?;
+    let orbital = get_staking(&deployment.pool, 1)?.get_alanes_id();
+
+    let split = create_tx(
+        Witness::new(),
+        outpoint(&stake, 0),
+        vec![transfer(vec![edict(&orbital, 1, 1)])],
+    );
+    let renew = create_tx(
+        Witness::new(),
+        outpoint(&split, 1),
+        vec![call(Cellpack {
+            target: orbital,
+            inputs: vec![1011, 1],
+        })],
+    );
+    index_txs(STAKING_BLOCK + 1, vec![split, renew.clone()])?;
+
+    // leaving a renewing position when its second period ends is not early
+    let unstake = create_tx(
+        Witness::new(),
+        outpoint(&renew, 0),
+        vec![call(Cellpack {
+            target: orbital,
+            inputs: vec![1004],
+        })],
+    );
+    index_txs(STAKING_HEIGHT as u32 + 144 * 60, vec![unstake])?;
+    let redemption: serde_json::Value =
+        serde_json::from_slice(&view_call(&deployment.pool, vec![1012, 1])?)?;
+    assert_eq!(redemption["brc20_value"], "800000000");
+    assert_eq!(redemption["penalty_value"], "0");
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_compound_claimed_coins() -> Result<()> {
+    let deployment = deploy_pool()?;
+    let stake = stake_tx(&deployment.pool, deployment.auth_outpoint, 30, 50000);
+    index_txs(STAKING_BLOCK, vec![stake.clone()])?;
+    let orbital = get_staking(&deployment.pool, 1)?.get_alanes_id();
+
+    let split = create_tx(
+        Witness::new(),
+        outpoint(&stake, 0),
+        vec![transfer(vec![edict(&orbital, 1, 1)])],
+    );
+    let claim = create_tx(
+        Witness::new(),
+        outpoint(&split, 1),
+        vec![call(Cellpack {
+            target: orbital,
+            inputs: vec![1005],
+        })],
+    );
+    let claim_height = (STAKING_HEIGHT + 144 * 3) as u32;
+    index_txs(STAKING_BLOCK + 1, vec![split])?;
+    let block = index_txs(claim_height, vec![claim.clone()])?;
+    let claimed = balance_of(&block, 1, 0, &deployment.coin)?;
+    assert!(claimed > 0);
+
+    // the claimed coins go back into the pool as a new position
+    let compound = create_tx(
+        Witness::new(),
+        outpoint(&claim, 0),
+        vec![call(Cellpack {
+            target: deployment.pool,
+            inputs: vec![57, 30],
+        })],
+    );
+    let block = index_txs(claim_height + 1, vec![compound.clone()])?;
+    let staking = get_staking(&deployment.pool, 2)?;
+    assert!(staking.is_alkane_staking());
+    assert_eq!(staking.get_token_id(), deployment.coin);
+    assert_eq!(staking.staking_value, claimed);
+    assert_eq!(staking.staking_height, (claim_height + 1) as u64);
+    let compounded = staking.get_alanes_id();
+    assert_eq!(balance_of(&block, 1, 0, &compounded)?, 1);
+    assert_eq!(balance_of(&block, 1, 0, &orbital)?, 1);
+    assert_eq!(balance_of(&block, 1, 0, &deployment.coin)?, 0);
+
+    // unstaking hands the coins back without a BRC-20 redemption
+    let split = create_tx(
+        Witness::new(),
+        outpoint(&compound, 0),
+        vec![transfer(vec![edict(&compounded, 1, 1)])],
+    );
+    let unstake = create_tx(
+        Witness::new(),
+        outpoint(&split, 1),
+        vec![call(Cellpack {
+            target: compounded,
+            inputs: vec![1004],
+        })],
+    );
+    let block = index_txs(claim_height + 144, vec![split, unstake])?;
+    assert_eq!(balance_of(&block, 2, 0, &deployment.coin)?, claimed);
+    assert_eq!(balance_of(&block, 2, 0, &compounded)?, 1);
+    assert!(view_call(&deployment.pool, vec![1012, 2]).is_err());
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_stake_whitelisted_token() -> Result<()> {
+    let deployment = deploy_pool()?;
+    let (partner, partner_outpoint) = deploy_token(DEPLOY_HEIGHT + 2, "Partner", 1000000)?;
+
+    let whitelist = create_tx(
+        payload_witness(
+            format!(r#"[{{"id":[{},{}],"ratio":"0.5","decimals":6}}]"#, partner.block, partner.tx)
+                .into_bytes(),
+        ),
+        deployment.auth_outpoint,
+        vec![call(Cellpack {
+            target: deployment.pool,
+            inputs: vec![80],
+        })],
+    );
+    let stake = create_tx(
+        Witness::new(),
+        partner_outpoint,
+        vec![call(Cellpack {
+            target: deployment.pool,
+            inputs: vec![58, 30],
+        })],
+    );
+    let block = index_txs(STAKING_BLOCK, vec![whitelist, stake.clone()])?;
+    let whitelist: serde_json::Value =
+        serde_json::from_slice(&view_call(&deployment.pool, vec![1015])?)?;
+    assert_eq!(whitelist[0]["ratio"], "0.5");
+
+    let staking = get_staking(&deployment.pool, 1)?;
+    assert_eq!(staking.get_token_id(), partner);
+    assert_eq!(staking.staking_value, 1000000);
+    // amounts use the decimals registered in the whitelist, not the token's reply
+    let attributes: serde_json::Value =
+        serde_json::from_slice(&view_call(&deployment.pool, vec![1002, 1])?)?;
+    assert_eq!(attributes["brc20_value_formatted"], "1");
+    let orbital = staking.get_alanes_id();
+    assert_eq!(balance_of(&block, 2, 0, &orbital)?, 1);
+    assert_eq!(balance_of(&block, 2, 0, &partner)?, 0);
+
+    let (mined, _, _) = get_profit(&deployment.pool, 1, (STAKING_BLOCK + 144) as u128)?;
+    assert!(mined > 0);
+
+    // the custodied tokens come back on unstaking
+    let unstake = create_tx(
+        Witness::new(),
+        outpoint(&stake, 0),
+        vec![call(Cellpack {
+            target: orbital,
+            inputs: vec![1004],
+        })],
+    );
+    let block = index_txs(STAKING_BLOCK + 144, vec![unstake])?;
+    assert_eq!(balance_of(&block, 1, 0, &partner)?, 1000000);
+    assert_eq!(balance_of(&block, 1, 0, &orbital)?, 1);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_top_up_and_extend_period() -> Result<()> {
+    let deployment = deploy_pool()?;
+    let (partner, partner_outpoint) = deploy_token(DEPLOY_HEIGHT + 2, "Partner", 3000000)?;
+
+    let whitelist = create_tx(
+        payload_witness(format!(r#"[{{"id":[{},{}]}}]"#, partner.block, partner.tx).into_bytes()),
+        deployment.auth_outpoint,
+        vec![call(Cellpack {
+            target: deployment.pool,
+            inputs: vec![80],
+        })],
+    );
+    // stake a third of the tokens, the rest stays on output 0
+    let split = create_tx(
+        Witness::new(),
+        partner_outpoint,
+        vec![transfer(vec![edict(&partner, 1000000, 1)])],
+    );
+    let stake = create_tx(
+        Witness::new(),
+        outpoint(&split, 1),
+        vec![call(Cellpack {
+            target: deployment.pool,
+            inputs: vec![58, 30],
+        })],
+    );
+    index_txs(STAKING_BLOCK, vec![whitelist, split.clone(), stake.clone()])?;
+    let orbital = get_staking(&deployment.pool, 1)?.get_alanes_id();
+
+    // the orbital and the remaining tokens are spent together
+    let top_up_height = STAKING_BLOCK + 144 * 2;
+    let (mined_before, _, _) = get_profit(&deployment.pool, 1, top_up_height as u128)?;
+    let mut top_up = create_tx(
+        Witness::new(),
+        outpoint(&stake, 0),
+        vec![call(Cellpack {
+            target: orbital,
+            inputs: vec![1007],
+        })],
+    );
+    top_up.input.push(bitcoin::TxIn {
+        previous_output: outpoint(&split, 0),
+        ..top_up.input[0].clone()
+    });
+    let block = index_txs(top_up_height, vec![top_up.clone()])?;
+    assert_eq!(balance_of(&block, 1, 0, &orbital)?, 1);
+    assert_eq!(balance_of(&block, 1, 0, &partner)?, 0);
+    let staking = get_staking(&deployment.pool, 1)?;
+    assert_eq!(staking.staking_value, 3000000);
+    // rewards of the days before the top up are unchanged
+    assert_eq!(get_profit(&deployment.pool, 1, top_up_height as u128)?.0, mined_before);
+
+    let extend = create_tx(
+        Witness::new(),
+        outpoint(&top_up, 0),
+        vec![call(Cellpack {
+            target: orbital,
+            inputs: vec![1008, 90],
+        })],
+    );
+    index_txs(top_up_height + 1, vec![extend])?;
+    let staking = get_staking(&deployment.pool, 1)?;
+    assert_eq!(staking.period, 90);
+
+    // a lone staker keeps mining the whole volume past the original 30 days
+    let (mined_day_40, _, _) = get_profit(&deployment.pool, 1, (STAKING_BLOCK + 144 * 40) as u128)?;
+    let (mined_day_41, _, _) = get_profit(&deployment.pool, 1, (STAKING_BLOCK + 144 * 41) as u128)?;
+    assert!(mined_day_41 > mined_day_40);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_split_and_merge() -> Result<()> {
+    let deployment = deploy_pool()?;
+    let (partner, partner_outpoint) = deploy_token(DEPLOY_HEIGHT + 2, "Partner", 2000000)?;
+
+    let whitelist = create_tx(
+        payload_witness(format!(r#"[{{"id":[{},{}]}}]"#, partner.block, partner.tx).into_bytes()),
+        deployment.auth_outpoint,
+        vec![call(Cellpack {
+            target: deployment.pool,
+            inputs: vec![80],
+        })],
+    );
+    let stake = create_tx(
+        Witness::new(),
+        partner_outpoint,
+        vec![call(Cellpack {
+            target: deployment.pool,
+            inputs: vec![58, 30],
+        })],
+    );
+    index_txs(STAKING_BLOCK, vec![whitelist, stake.clone()])?;
+    let orbital = get_staking(&deployment.pool, 1)?.get_alanes_id();
+
+    let split_height = STAKING_BLOCK + 144 * 2;
+    let day_10 = (STAKING_BLOCK + 144 * 10) as u128;
+    let expected = [split_height as u128, day_10]
+        .map(|height| get_profit(&deployment.pool, 1, height).unwrap().0);
+    let split = create_tx(
+        Witness::new(),
+        outpoint(&stake, 0),
+        vec![call(Cellpack {
+            target: orbital,
+            inputs: vec![1009, 500000],
+        })],
+    );
+    let block = index_txs(split_height, vec![split.clone()])?;
+    let split_staking = get_staking(&deployment.pool, 2)?;
+    let split_orbital = split_staking.get_alanes_id();
+    assert_eq!(balance_of(&block, 1, 0, &orbital)?, 1);
+    assert_eq!(balance_of(&block, 1, 0, &split_orbital)?, 1);
+    assert_eq!(split_staking.staking_value, 500000);
+    assert_eq!(get_staking(&deployment.pool, 1)?.staking_value, 1500000);
+
+    // the two positions share the rewards of the original one, before and after the split
+    for (height, expected) in [split_height as u128, day_10].into_iter().zip(expected) {
+        let (mined, _, _) = get_profit(&deployment.pool, 1, height)?;
+        let (split_mined, _, _) = get_profit(&deployment.pool, 2, height)?;
+        assert!(mined + split_mined <= expected && mined + split_mined + 1 >= expected);
+    }
+
+    let merge = create_tx(
+        Witness::new(),
+        outpoint(&split, 0),
+        vec![call(Cellpack {
+            target: orbital,
+            inputs: vec![1010],
+        })],
+    );
+    let merge_height = split_height + 144;
+    let (mined_before, _, _) = get_profit(&deployment.pool, 1, day_10)?;
+    let (split_mined_before, _, _) = get_profit(&deployment.pool, 2, day_10)?;
+    let block = index_txs(merge_height, vec![merge])?;
+    assert_eq!(balance_of(&block, 1, 0, &orbital)?, 1);
+    assert_eq!(balance_of(&block, 1, 0, &split_orbital)?, 0);
+    assert_eq!(get_staking(&deployment.pool, 1)?.staking_value, 2000000);
+    assert_eq!(get_staking(&deployment.pool, 2)?.staking_value, 0);
+    let (mined, _, _) = get_profit(&deployment.pool, 1, day_10)?;
+    assert!(mined + 1 >= mined_before + split_mined_before);
+    assert_eq!(get_profit(&deployment.pool, 2, day_10)?.0, 0);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_split_large_brc20() -> Result<()> {
+    let deployment = deploy_pool()?;
+    // 1e29 is beyond what Decimal can hold
+    let staking_value = 100_000_000_000u128;
+    let brc20_value = staking_value * 10u128.pow(18);
+    let stake = create_tx(
+        payload_witness(staking_payload(0, brc20_value, staking_value, 30, [8; 32], &AlkaneId { block: 0, tx: 0 }, STAKING_HEIGHT)),
+        deployment.auth_outpoint,
+        vec![call(Cellpack {
+            target: deployment.pool,
+            inputs: vec![50],
+        })],
+    );
+    index_txs(STAKING_BLOCK, vec![stake.clone()])?;
+    let orbital = get_staking(&deployment.pool, 1)?.get_alanes_id();
+
+    let part = create_tx(
+        Witness::new(),
+        outpoint(&stake, 0),
+        vec![transfer(vec![edict(&orbital, 1, 1)])],
+    );
+    let split = create_tx(
+        Witness::new(),
+        outpoint(&part, 1),
+        vec![call(Cellpack {
+            target: orbital,
+            inputs: vec![1009, staking_value / 4],
+        })],
+    );
+    index_txs(STAKING_BLOCK + 1, vec![part, split])?;
+    assert_eq!(get_staking(&deployment.pool, 2)?.brc20_value, brc20_value / 4);
+    assert_eq!(get_staking(&deployment.pool, 1)?.brc20_value, brc20_value / 4 * 3);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_auto_renew() -> Result<()> {
+    let deployment = deploy_pool()?;
+    let (partner, partner_outpoint) = deploy_token(DEPLOY_HEIGHT + 2, "Partner", 1000000)?;
+
+    let whitelist = create_tx(
+        payload_witness(format!(r#"[{{"id":[{},{}]}}]"#, partner.block, partner.tx).into_bytes()),
+        deployment.auth_outpoint,
+        vec![call(Cellpack {
+            target: deployment.pool,
+            inputs: vec![80],
+        })],
+    );
+    let stake = create_tx(
+        Witness::new(),
+        partner_outpoint,
+        vec![call(Cellpack {
+            target: deployment.pool,
+            inputs: vec![58, 30],
+        })],
+    );
+    index_txs(STAKING_BLOCK, vec![whitelist, stake.clone()])?;
+    let orbital = get_staking(&deployment.pool, 1)?.get_alanes_id();
+
+    let renew = create_tx(
+        Witness::new(),
+        outpoint(&stake, 0),
+        vec![call(Cellpack {
+            target: orbital,
+            inputs: vec![1011, 1],
+        })],
+    );
+    index_txs(STAKING_BLOCK + 144, vec![renew.clone()])?;
+    assert!(get_staking(&deployment.pool, 1)?.auto_renew);
+
+    // the position keeps mining past the end of its first period
+    let (mined_day_40, _, _) = get_profit(&deployment.pool, 1, (STAKING_BLOCK + 144 * 40) as u128)?;
+    let (mined_day_41, _, _) = get_profit(&deployment.pool, 1, (STAKING_BLOCK + 144 * 41) as u128)?;
+    assert!(mined_day_41 > mined_day_40);
+
+    // turned off during the second period it expires at the end of that period
+    let stop = create_tx(
+        Witness::new(),
+        outpoint(&renew, 0),
+        vec![call(Cellpack {
+            target: orbital,
+            inputs: vec![1011, 0],
+        })],
+    );
+    index_txs(STAKING_BLOCK + 144 * 40, vec![stop])?;
+    let staking = get_staking(&deployment.pool, 1)?;
+    assert!(!staking.auto_renew);
+    assert_eq!(staking.renewals, 1);
+    assert_eq!(staking.get_expire_height(), staking.staking_height + 144 * 60);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_pause_and_emergency_withdraw() -> Result<()> {
+    let deployment = deploy_pool()?;
+    let stake = stake_tx(&deployment.pool, deployment.auth_outpoint, 30, 50000);
+    index_txs(STAKING_BLOCK, vec![stake.clone()])?;
+    let orbital = get_staking(&deployment.pool, 1)?.get_alanes_id();
+
+    let split = create_tx(
+        Witness::new(),
+        outpoint(&stake, 0),
+        vec![transfer(vec![edict(&orbital, 1, 1)])],
+    );
+    let pause = admin_tx(&deployment.pool, outpoint(&split, 0), vec![82, 50, 1]);
+    let paused_stake = stake_tx(&deployment.pool, outpoint(&pause, 0), 30, 50000);
+    index_txs(STAKING_BLOCK + 1, vec![split.clone(), pause, paused_stake.clone()])?;
+    assert_revert_context(
+        &OutPoint {
+            txid: paused_stake.compute_txid(),
+            vout: first_protostone_vout(&paused_stake),
+        },
+        "opcode is paused",
+    )?;
+    let state: serde_json::Value = serde_json::from_slice(&view_call(&deployment.pool, vec![1016])?)?;
+    assert_eq!(state["paused"], serde_json::json!(["50"]));
+    assert_eq!(state["emergency"], false);
+
+    // in emergency mode unstaking is halted, the holder exits with the principal only
+    let emergency = admin_tx(&deployment.pool, outpoint(&paused_stake, 0), vec![83, 1]);
+    let unstake = create_tx(
+        Witness::new(),
+        outpoint(&split, 1),
+        vec![call(Cellpack {
+            target: orbital,
+            inputs: vec![1004],
+        })],
+    );
+    let exit = create_tx(
+        Witness::new(),
+        outpoint(&unstake, 0),
+        vec![call(Cellpack {
+            target: orbital,
+            inputs: vec![1012],
+        })],
+    );
+    let exit_height = STAKING_BLOCK + 144 * 2;
+    index_txs(exit_height, vec![emergency, unstake, exit])?;
+    let staking = get_staking(&deployment.pool, 1)?;
+    assert_eq!(staking.unstaking_height, exit_height as u64);
+    let redemption: serde_json::Value =
+        serde_json::from_slice(&view_call(&deployment.pool, vec![1012, 1])?)?;
+    assert_eq!(redemption["brc20_value"], "800000000");
+    assert_eq!(redemption["penalty_value"], "0");
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_migrate_storage() -> Result<()> {
+    let deployment = deploy_pool()?;
+    let version: serde_json::Value = serde_json::from_slice(&view_call(&deployment.pool, vec![1017])?)?;
+    assert_eq!(version["schema_version"], version["latest"]);
+
+    let stake = stake_tx(&deployment.pool, deployment.auth_outpoint, 30, 50000);
+    index_txs(STAKING_BLOCK, vec![stake.clone()])?;
+    let orbital = get_staking(&deployment.pool, 1)?.get_alanes_id();
+    let split = create_tx(
+        Witness::new(),
+        outpoint(&stake, 0),
+        vec![transfer(vec![edict(&orbital, 1, 1)])],
+    );
+    let migrate = admin_tx(&deployment.pool, outpoint(&split, 0), vec![84, 10]);
+    index_txs(STAKING_BLOCK + 1, vec![split, migrate])?;
+    let version: serde_json::Value = serde_json::from_slice(&view_call(&deployment.pool, vec![1017])?)?;
+    assert_eq!(version["migrated"], "1");
+    assert_eq!(version["total"], "1");
+    assert_eq!(get_staking(&deployment.pool, 1)?.staking_value, 50000);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_sweep_dust() -> Result<()> {
+    let deployment = deploy_pool_with_config(
+        br#"{"mining_last_height":1169,"treasury":[2,999]}"#.to_vec(),
+    )?;
+    let first = stake_tx(&deployment.pool, deployment.auth_outpoint, 30, 50000);
+    let second = stake_tx(&deployment.pool, outpoint(&first, 0), 30, 60000);
+    let third = stake_tx(&deployment.pool, outpoint(&second, 0), 30, 70000);
+    index_txs(STAKING_BLOCK, vec![first, second, third.clone()])?;
+    let orbitals = (1..=3)
+        .map(|index| Ok(get_staking(&deployment.pool, index)?.get_alanes_id()))
+        .collect::<Result<Vec<_>>>()?;
+    let split = create_tx(
+        Witness::new(),
+        outpoint(&third, 0),
+        vec![transfer(orbitals.iter().map(|orbital| edict(orbital, 1, 1)).collect())],
+    );
+    let early = admin_tx(&deployment.pool, outpoint(&split, 0), vec![85]);
+    index_txs(STAKING_BLOCK + 1, vec![split, early.clone()])?;
+    assert_revert_context(
+        &OutPoint {
+            txid: early.compute_txid(),
+            vout: first_protostone_vout(&early),
+        },
+        "Mining not ended",
+    )?;
+
+    // no dust is recorded before the positions claim
+    let stats: serde_json::Value = serde_json::from_slice(&view_call(&deployment.pool, vec![1018])?)?;
+    assert_eq!(stats["allocated"], "0");
+    assert_eq!(stats["dust"], "0");
+
+    // each orbital is split off on its own to claim after mining has ended
+    let mut rest = outpoint(&split, 1);
+    let mut txs = vec![];
+    for orbital in &orbitals {
+        let part = create_tx(Witness::new(), rest, vec![transfer(vec![edict(orbital, 1, 1)])]);
+        let claim = create_tx(
+            Witness::new(),
+            outpoint(&part, 1),
+            vec![call(Cellpack {
+                target: *orbital,
+                inputs: vec![1005],
+            })],
+        );
+        rest = outpoint(&part, 0);
+        txs.extend([part, claim]);
+    }
+    // the floored rewards of the three positions leave some dust behind
+    txs.push(admin_tx(&deployment.pool, outpoint(&early, 0), vec![85]));
+    index_txs(1170 + 144, txs)?;
+    let stats: serde_json::Value = serde_json::from_slice(&view_call(&deployment.pool, vec![1018])?)?;
+    let emitted: u128 = stats["emitted"].as_str().unwrap().parse()?;
+    let allocated: u128 = stats["allocated"].as_str().unwrap().parse()?;
+    let swept: u128 = stats["swept"].as_str().unwrap().parse()?;
+    assert!(emitted > allocated);
+    assert!(swept <= emitted - allocated && emitted - allocated - swept <= 1);
+    assert_eq!(stats["dust"], "0");
+    let penalty: serde_json::Value = serde_json::from_slice(&view_call(&deployment.pool, vec![1014])?)?;
+    assert_eq!(penalty["treasury"], stats["swept"]);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn test_formatted_amounts() -> Result<()> {
+    let deployment = deploy_pool()?;
+    let stake = stake_tx(&deployment.pool, deployment.auth_outpoint, 30, 50000);
+    index_txs(STAKING_BLOCK, vec![stake])?;
+    assert_eq!(view_call(&deployment.coin, vec![1004])?, 8u128.to_le_bytes().to_vec());
+
+    let height = STAKING_BLOCK as u128 + 144;
+    let profit: Vec<String> =
+        serde_json::from_slice(&view_call(&deployment.pool, vec![53, 1, height])?)?;
+    assert_eq!(profit.len(), 6);
+    assert_eq!(profit[3], format_amount(profit[0].parse()?, 8));
+    assert_eq!(profit[5], "0");
+
+    let attributes: serde_json::Value =
+        serde_json::from_slice(&view_call(&deployment.pool, vec![1002, 1])?)?;
+    assert_eq!(attributes["brc20_value_formatted"], "0.0000000008");
+    assert_eq!(attributes["withdraw_coin_value_formatted"], "0");
+    Ok(())
+}
+
+fn reveal_tx(body: &str) -> bitcoin::Transaction {
+    let script = Builder::new()
+        .push_opcode(OP_0)
+        .push_opcode(OP_IF)
+        .push_slice(b"ord")
+        .push_slice([1u8])
+        .push_slice(b"text/plain;charset=utf-8")
+        .push_opcode(OP_0)
+        .push_slice(PushBytesBuf::try_from(body.as_bytes().to_vec()).unwrap())
+        .push_opcode(OP_ENDIF)
+        .into_script();
+    let mut witness = Witness::new();
+    witness.push(script.as_bytes());
+    witness.push([0xc0u8; 33]);
+    single_io_tx(OutPoint::null(), witness, ScriptBuf::new_op_return([1u8; 4]))
+}
+
+fn single_io_tx(previous_output: OutPoint, witness: Witness, script_pubkey: ScriptBuf) -> bitcoin::Transaction {
+    bitcoin::Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness,
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(546),
+            script_pubkey,
+        }],
+    }
+}
+
+/// Transfer of the inscription to the deposit script with the depositor's change on
+/// output 1
+fn deposit_tx(reveal: &bitcoin::Transaction, deposit_script: &ScriptBuf) -> bitcoin::Transaction {
+    let mut tx = single_io_tx(outpoint(reveal, 0), Witness::new(), deposit_script.clone());
+    tx.output.push(TxOut {
+        value: Amount::from_sat(546),
+        script_pubkey: ScriptBuf::new_op_return([7u8; 4]),
+    });
+    tx
+}
+
+/// Adds an input spending the depositor's output of `deposit`
+fn spending(mut tx: bitcoin::Transaction, deposit: &bitcoin::Transaction) -> bitcoin::Transaction {
+    tx.input.push(TxIn {
+        previous_output: outpoint(deposit, 1),
+        ..tx.input[0].clone()
+    });
+    tx
+}
+
+fn mine_header(prev_blockhash: BlockHash, merkle_root: TxMerkleNode, time: u32) -> Header {
+    let mut header = Header {
+        version: BlockVersion::TWO,
+        prev_blockhash,
+        merkle_root,
+        time,
+        bits: CompactTarget::from_consensus(0x207fffff),
+        nonce: 0,
+    };
+    while header.validate_pow(header.target()).is_err() {
+        header.nonce += 1;
+    }
+    header
+}
+
+fn headers_payload(headers: &[Header]) -> Vec<u8> {
+    headers.iter().flat_map(|header| bitcoin::consensus::serialize(header)).collect()
+}
+
+fn witness_tx(pool: &AlkaneId, previous_output: OutPoint, payload: Vec<u8>, inputs: Vec<u128>) -> bitcoin::Transaction {
+    create_tx(
+        payload_witness(payload),
+        previous_output,
+        vec![call(Cellpack {
+            target: *pool,
+            inputs,
+        })],
+    )
+}
+
+#[wasm_bindgen_test]
+fn test_staking_with_proof() -> Result<()> {
+    let deployment = deploy_pool()?;
+    let pool = deployment.pool;
+    let deposit_script = ScriptBuf::new_op_return([9u8; 4]);
+
+    // two transfer inscriptions sent to the deposit script in the block at 456, the
+    // second one tops up the position staked with the first
+    let reveal = reveal_tx(r#"{"p":"brc-20","op":"transfer","tick":"sats","amt":"50000"}"#);
+    let deposit = deposit_tx(&reveal, &deposit_script);
+    let top_up_reveal = reveal_tx(r#"{"p":"brc-20","op":"transfer","tick":"sats","amt":"25000"}"#);
+    let top_up_deposit = deposit_tx(&top_up_reveal, &deposit_script);
+    let txids = [deposit.compute_txid().to_byte_array(), top_up_deposit.compute_txid().to_byte_array()];
+    let deposit_root = TxMerkleNode::from_byte_array(sha256d::Hash::hash(&txids.concat()).to_byte_array());
+
+    let checkpoint = mine_header(BlockHash::all_zeros(), TxMerkleNode::all_zeros(), 1700000000);
+    let orphan = mine_header(checkpoint.block_hash(), TxMerkleNode::all_zeros(), 1700000600);
+    let mut chain = vec![mine_header(checkpoint.block_hash(), deposit_root, 1700000600)];
+    for i in 1..6 {
+        let prev = chain[i - 1].block_hash();
+        chain.push(mine_header(prev, TxMerkleNode::all_zeros(), 1700000600 + 600 * i as u32));
+    }
+
+    let set_checkpoint = witness_tx(&pool, deployment.auth_outpoint, bitcoin::consensus::serialize(&checkpoint), vec![70, 455]);
+    let set_script = witness_tx(&pool, outpoint(&set_checkpoint, 0), deposit_script.to_bytes(), vec![72]);
+    let submit_orphan = witness_tx(&pool, OutPoint::null(), headers_payload(&[orphan]), vec![71]);
+    index_txs(STAKING_BLOCK, vec![set_checkpoint, set_script.clone(), submit_orphan])?;
+    assert!(view_string(&pool, vec![1005])?.starts_with("456:"));
+
+    // the heavier branch replaces the orphan, a branch with equal work does not
+    let submit_chain = witness_tx(&pool, OutPoint::null(), headers_payload(&chain), vec![71]);
+    let rival = mine_header(chain[4].block_hash(), TxMerkleNode::all_zeros(), 1700009999);
+    let submit_rival = witness_tx(&pool, OutPoint::null(), headers_payload(&[rival]), vec![71]);
+    index_txs(STAKING_BLOCK + 1, vec![submit_chain, submit_rival.clone()])?;
+    assert_eq!(view_string(&pool, vec![1005])?, format!("461:{}", chain[5].block_hash()));
+    assert_revert_context(
+        &OutPoint {
+            txid: submit_rival.compute_txid(),
+            vout: first_protostone_vout(&submit_rival),
+        },
+        "branch has less work than the tracked chain",
+    )?;
+
+    let proof = DepositProof {
+        brc20_index: 0,
+        period: 30,
+        invite_alkanes_id: [0, 0],
+        height: 456,
+        tx_index: 0,
+        merkle_branch: vec![txids[1]],
+        reveal_tx: bitcoin::consensus::serialize(&reveal),
+        transfer_tx: bitcoin::consensus::serialize(&deposit),
+    };
+
+    // the inscription must be attested before it can be staked
+    let unattested = witness_tx(&pool, OutPoint::null(), proof.to_vec8(), vec![55]);
+    index_txs(STAKING_BLOCK + 2, vec![unattested.clone()])?;
+    assert_revert_context(
+        &OutPoint {
+            txid: unattested.compute_txid(),
+            vout: first_protostone_vout(&unattested),
+        },
+        "inscription not attested",
+    )?;
+
+    let attest = witness_tx(
+        &pool,
+        outpoint(&set_script, 0),
+        [reveal.compute_wtxid().to_byte_array(), top_up_reveal.compute_wtxid().to_byte_array()].concat(),
+        vec![86],
+    );
+    // the proof alone can't claim the deposit, the depositor's output must be spent too
+    let front_run = witness_tx(&pool, outpoint(&unattested, 0), proof.to_vec8(), vec![55]);
+    let stake = spending(witness_tx(&pool, OutPoint::null(), proof.to_vec8(), vec![55]), &deposit);
+    let replay = spending(witness_tx(&pool, outpoint(&front_run, 0), proof.to_vec8(), vec![55]), &deposit);
+    let block = index_txs(STAKING_BLOCK + 3, vec![attest.clone(), front_run.clone(), stake.clone(), replay.clone()])?;
+    assert_revert_context(
+        &OutPoint {
+            txid: front_run.compute_txid(),
+            vout: first_protostone_vout(&front_run),
+        },
+        "claim does not spend the depositor's output of the deposit",
+    )?;
+    let staking = get_staking(&pool, 1)?;
+    assert_eq!(staking.brc20_value, 50000 * 10u128.pow(18));
+    assert_eq!(staking.staking_value, 50000);
+    assert_eq!(staking.staking_height, 456);
+    assert_eq!(balance_of(&block, 3, 0, &staking.get_alanes_id())?, 1);
+    assert_revert_context(
+        &OutPoint {
+            txid: replay.compute_txid(),
+            vout: first_protostone_vout(&replay),
+        },
+        "deposit already staked",
+    )?;
+
+    // the orbital tops its position up with the second deposit
+    let orbital = staking.get_alanes_id();
+    let top_up_proof = DepositProof {
+        tx_index: 1,
+        merkle_branch: vec![txids[0]],
+        reveal_tx: bitcoin::consensus::serialize(&top_up_reveal),
+        transfer_tx: bitcoin::consensus::serialize(&top_up_deposit),
+        ..proof
+    };
+    let top_up = spending(
+        witness_tx(&orbital, outpoint(&stake, 0), top_up_proof.to_vec8(), vec![1007]),
+        &top_up_deposit,
+    );
+    // operators can't register a deposit already staked with a proof either
+    let operator_stake = create_tx(
+        payload_witness(staking_payload(0, 800000000, 50000, 30, txids[0], &AlkaneId { block: 0, tx: 0 }, STAKING_HEIGHT)),
+        outpoint(&attest, 0),
+        vec![call(Cellpack {
+            target: pool,
+            inputs: vec![50],
+        })],
+    );
+    let block = index_txs(STAKING_BLOCK + 4, vec![top_up.clone(), operator_stake.clone()])?;
+    assert_eq!(balance_of(&block, 1, 0, &orbital)?, 1);
+    assert_revert_context(
+        &OutPoint {
+            txid: operator_stake.compute_txid(),
+            vout: first_protostone_vout(&operator_stake),
+        },
+        "deposit already staked",
+    )?;
+    let staking = get_staking(&pool, 1)?;
+    assert_eq!(staking.brc20_value, 75000 * 10u128.pow(18));
+    assert_eq!(staking.staking_value, 75000);
+
+    // a deposit tops up only once
+    let top_up_replay = spending(
+        witness_tx(&orbital, outpoint(&top_up, 0), top_up_proof.to_vec8(), vec![1007]),
+        &top_up_deposit,
+    );
+    index_txs(STAKING_BLOCK + 5, vec![top_up_replay.clone()])?;
+    assert_revert_context(
+        &OutPoint {
+            txid: top_up_replay.compute_txid(),
+            vout: first_protostone_vout(&top_up_replay),
+        },
+        "deposit already staked",
+    )?;
+    assert_eq!(get_staking(&pool, 1)?.staking_value, 75000);
+    Ok(())
+}